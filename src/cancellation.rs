@@ -0,0 +1,183 @@
+#![deny(unsafe_code)]
+
+use core::cell::Cell;
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+/// Thread-unsafe cancellation signal, can be awaited by any number of tasks.
+///
+/// Tokens form a tree: cancelling a token cancels all its children, but not its parent.
+/// To avoid storing a waker per waiting task, cancellation wakes all tasks of the
+/// executor, and they check their tokens again.
+pub struct CancellationToken<'a> {
+    parent: Option<&'a CancellationToken<'a>>,
+    cancelled: Cell<bool>,
+    // Any waker of a task waiting for this token or one of its children.
+    waker: Cell<Option<Waker>>,
+    waiters: Cell<usize>,
+}
+
+impl Debug for CancellationToken<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .field("waiters", &self.waiters.get())
+            .finish()
+    }
+}
+
+impl Default for CancellationToken<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> CancellationToken<'a> {
+    /// Creates root token.
+    pub const fn new() -> Self {
+        Self {
+            parent: None,
+            cancelled: Cell::new(false),
+            waker: Cell::new(None),
+            waiters: Cell::new(0),
+        }
+    }
+
+    /// Creates token that is cancelled together with this one.
+    pub fn child(&'a self) -> CancellationToken<'a> {
+        CancellationToken {
+            parent: Some(self),
+            ..Self::new()
+        }
+    }
+
+    /// Cancels this token and all its children.
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+
+        if let Some(waker) = self.waker.take() {
+            // Executor of the waiting tasks may be gone already.
+            let _ = crate::waker::from_waker(&waker)
+                .with_executor(|executor| executor.wake_all_tasks());
+        }
+    }
+
+    /// Checks if this token or any of its parents is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get() || self.parent.is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Waits for the token to be cancelled.
+    pub async fn cancelled(&self) {
+        CancelledFuture {
+            token: self,
+            registered: false,
+        }
+        .await
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &CancellationToken<'a>> {
+        core::iter::successors(Some(self), |token| token.parent)
+    }
+}
+
+struct CancelledFuture<'a, 'b> {
+    token: &'b CancellationToken<'a>,
+    registered: bool,
+}
+
+impl Future for CancelledFuture<'_, '_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        // Cancelling any ancestor must wake us too.
+        for token in self.token.ancestors() {
+            token.waker.set(Some(cx.waker().clone()));
+            if !self.registered {
+                token.waiters.update(|waiters| waiters + 1);
+            }
+        }
+        self.registered = true;
+
+        Poll::Pending
+    }
+}
+
+impl Drop for CancelledFuture<'_, '_> {
+    fn drop(&mut self) {
+        if self.registered {
+            for token in self.token.ancestors() {
+                token.waiters.update(|waiters| waiters - 1);
+                if token.waiters.get() == 0 {
+                    // Don't keep waker of a possibly finished executor.
+                    token.waker.set(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::join;
+
+    use super::*;
+    use crate::test_utils::block_on;
+    use crate::time::Duration;
+
+    #[test]
+    fn cancel_wakes_waiters() {
+        let token = CancellationToken::new();
+
+        let (a, b, _) = block_on(async {
+            join!(
+                async {
+                    token.cancelled().await;
+                    1
+                },
+                async {
+                    token.cancelled().await;
+                    2
+                },
+                async {
+                    crate::sleep(Duration::new(10)).await;
+                    token.cancel();
+                }
+            )
+        });
+
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(token.waiters.get(), 0);
+    }
+
+    #[test]
+    fn parent_cancels_child() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let grandchild = child.child();
+
+        child.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(grandchild.is_cancelled());
+
+        let other = parent.child();
+        let v = block_on(async {
+            join!(
+                async {
+                    other.cancelled().await;
+                    42
+                },
+                async { parent.cancel() }
+            )
+            .0
+        });
+
+        assert_eq!(v, 42);
+    }
+}
@@ -0,0 +1,279 @@
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Output of `select2`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Either<A, B> {
+    First(A),
+    Second(B),
+}
+
+/// Output of `select3`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Either3<A, B, C> {
+    First(A),
+    Second(B),
+    Third(C),
+}
+
+/// Output of `select4`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Either4<A, B, C, D> {
+    First(A),
+    Second(B),
+    Third(C),
+    Fourth(D),
+}
+
+// Futures are polled in argument order, so the first one wins if several are ready.
+// Executor doesn't tell which future was woken, each wakeup polls all of them.
+macro_rules! select {
+    ($(#[$attr:meta])* $name:ident, $select:ident, $either:ident, $($future:ident $field:ident $variant:ident),+) => {
+        $(#[$attr])*
+        pub fn $name<$($future: Future),+>($($field: $future),+) -> $select<$($future),+> {
+            $select { $($field),+ }
+        }
+
+        #[doc = concat!("Future returned by `", stringify!($name), "()`.")]
+        #[derive(Debug)]
+        #[must_use = "futures do nothing unless polled"]
+        pub struct $select<$($future),+> {
+            $($field: $future),+
+        }
+
+        impl<$($future: Future),+> Future for $select<$($future),+> {
+            type Output = $either<$($future::Output),+>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                // SAFETY: futures are never moved out of the pinned struct.
+                let this = unsafe { self.get_unchecked_mut() };
+
+                $(
+                    if let Poll::Ready(output) = unsafe { Pin::new_unchecked(&mut this.$field) }.poll(cx) {
+                        return Poll::Ready($either::$variant(output));
+                    }
+                )+
+
+                Poll::Pending
+            }
+        }
+    };
+}
+
+select!(
+    /// Waits for the first of two futures, dropping the other one.
+    ///
+    /// Racing an operation with `crate::sleep()` makes a cheap timeout:
+    /// the sleep only sets a deadline for the current task.
+    select2, Select2, Either, A a First, B b Second
+);
+select!(
+    /// Waits for the first of three futures, dropping the others.
+    select3, Select3, Either3, A a First, B b Second, C c Third
+);
+select!(
+    /// Waits for the first of four futures, dropping the others.
+    select4, Select4, Either4, A a First, B b Second, C c Third, D d Fourth
+);
+
+/// Waits for the first of `futures`, returning its index and output.
+pub fn select_array<F: Future, const N: usize>(futures: [F; N]) -> SelectArray<F, N> {
+    SelectArray { futures }
+}
+
+/// Future returned by `select_array()`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SelectArray<F, const N: usize> {
+    futures: [F; N],
+}
+
+impl<F: Future, const N: usize> Future for SelectArray<F, N> {
+    type Output = (usize, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: futures are never moved out of the pinned array.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        for (index, future) in this.futures.iter_mut().enumerate() {
+            if let Poll::Ready(output) = unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                return Poll::Ready((index, output));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Waits for all `futures`, returning their outputs in the same order.
+pub fn join_array<F: Future, const N: usize>(futures: [F; N]) -> JoinArray<F, N> {
+    JoinArray {
+        slots: futures.map(Slot::Pending),
+    }
+}
+
+/// Future returned by `join_array()`.
+#[must_use = "futures do nothing unless polled"]
+pub struct JoinArray<F: Future, const N: usize> {
+    slots: [Slot<F>; N],
+}
+
+impl<F: Future, const N: usize> Debug for JoinArray<F, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let done = self
+            .slots
+            .iter()
+            .filter(|slot| matches!(slot, Slot::Done(_)))
+            .count();
+
+        f.debug_struct("JoinArray")
+            .field("done", &done)
+            .finish_non_exhaustive()
+    }
+}
+
+enum Slot<F: Future> {
+    Pending(F),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future, const N: usize> Future for JoinArray<F, N> {
+    type Output = [F::Output; N];
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: pending futures are never moved, they are dropped in place when done.
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut all_done = true;
+
+        for slot in &mut this.slots {
+            if let Slot::Pending(future) = slot {
+                match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                    Poll::Ready(output) => *slot = Slot::Done(output),
+                    Poll::Pending => all_done = false,
+                }
+            }
+        }
+
+        if !all_done {
+            return Poll::Pending;
+        }
+
+        Poll::Ready(core::array::from_fn(|index| {
+            match core::mem::replace(&mut this.slots[index], Slot::Taken) {
+                Slot::Done(output) => output,
+                _ => panic!("join_array polled after completion"),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::mailbox::Mailbox;
+    use crate::sim::simulate;
+    use crate::time::{Duration, Instant};
+
+    async fn sleep_and_return<T>(ticks: i64, value: T) -> T {
+        crate::sleep(Duration::new(ticks)).await;
+        value
+    }
+
+    #[test]
+    fn select_with_timeout() {
+        let mbox = Mailbox::<u32>::new();
+        let results = Cell::new([None, None]);
+
+        let mut reader = pin!(async {
+            let timed_out = select2(mbox.read(), crate::sleep(Duration::new(100))).await;
+            let received = select2(mbox.read(), crate::sleep(Duration::new(100))).await;
+            results.set([Some(timed_out), Some(received)]);
+        });
+        let mut writer = pin!(async {
+            crate::sleep(Duration::new(150)).await;
+            mbox.post(7);
+        });
+
+        let end = simulate(
+            0,
+            [
+                LocalFutureObj::new(&mut reader),
+                LocalFutureObj::new(&mut writer),
+            ],
+        );
+
+        assert_eq!(
+            results.get(),
+            [Some(Either::Second(())), Some(Either::First(Ok(7)))]
+        );
+        assert_eq!(end, Instant::new(150));
+    }
+
+    #[test]
+    fn select_first_ready() {
+        let winners = Cell::new((None, None, None));
+
+        let mut f = pin!(async {
+            let three = select3(
+                sleep_and_return(30, 'a'),
+                sleep_and_return(10, 1),
+                sleep_and_return(20, "c"),
+            )
+            .await;
+            let four = select4(
+                sleep_and_return(30, 'a'),
+                sleep_and_return(40, 1),
+                sleep_and_return(20, "c"),
+                sleep_and_return(20, 4.0),
+            )
+            .await;
+            let array = select_array([
+                sleep_and_return(30, 'a'),
+                sleep_and_return(10, 'b'),
+                sleep_and_return(10, 'c'),
+            ])
+            .await;
+            winners.set((Some(three), Some(four), Some(array)));
+        });
+
+        simulate(0, [LocalFutureObj::new(&mut f)]);
+
+        assert_eq!(
+            winners.get(),
+            (
+                Some(Either3::Second(1)),
+                Some(Either4::Third("c")),
+                Some((1, 'b'))
+            )
+        );
+    }
+
+    #[test]
+    fn join_all() {
+        let outputs = Cell::new(None);
+
+        let mut f = pin!(async {
+            outputs.set(Some(
+                join_array([
+                    sleep_and_return(30, 'a'),
+                    sleep_and_return(10, 'b'),
+                    sleep_and_return(20, 'c'),
+                ])
+                .await,
+            ));
+        });
+
+        let end = simulate(0, [LocalFutureObj::new(&mut f)]);
+
+        assert_eq!(outputs.get(), Some(['a', 'b', 'c']));
+        assert_eq!(end, Instant::new(30));
+    }
+}
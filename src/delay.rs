@@ -0,0 +1,80 @@
+#![deny(unsafe_code)]
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::time::Duration;
+
+/// `DelayNs` implementation sleeping on the executor's timer.
+///
+/// Requested delays are rounded up to whole ticks, so they are never shorter than asked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Delay {
+    tick_hz: u32,
+}
+
+impl Delay {
+    /// Creates delay for an environment whose clock runs at `tick_hz` ticks per second.
+    pub const fn new(tick_hz: u32) -> Self {
+        Self { tick_hz }
+    }
+
+    /// Converts `amount` of `1 / per_second` second units into ticks, rounding up.
+    fn ticks(&self, amount: u32, per_second: u32) -> Duration {
+        let ticks = (u64::from(amount) * u64::from(self.tick_hz)).div_ceil(u64::from(per_second));
+        // The product is below 2^64 and `per_second` is at least 1000, so it fits.
+        Duration::new(ticks as i64)
+    }
+}
+
+impl DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        crate::sleep(self.ticks(ns, 1_000_000_000)).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        crate::sleep(self.ticks(us, 1_000_000)).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        crate::sleep(self.ticks(ms, 1_000)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::sim::simulate;
+    use crate::time::Instant;
+
+    #[test]
+    fn tick_conversion() {
+        let delay = Delay::new(32_768);
+
+        assert_eq!(delay.ticks(1, 1_000_000_000), Duration::new(1));
+        assert_eq!(delay.ticks(1_000, 1_000), Duration::new(32_768));
+        assert_eq!(delay.ticks(u32::MAX, 1_000), Duration::new(140_737_488_323));
+        assert_eq!(Delay::new(1_000).ticks(1_500, 1_000_000), Duration::new(2));
+    }
+
+    // Driver code written against the trait, unaware of the executor.
+    async fn blink(delay: &mut impl DelayNs) {
+        delay.delay_ms(5).await;
+        delay.delay_us(1_500).await;
+        delay.delay_ns(1).await;
+    }
+
+    #[test]
+    fn driver_delays() {
+        let mut delay = Delay::new(1_000);
+        let mut f = pin!(blink(&mut delay));
+
+        let end = simulate(0, [LocalFutureObj::new(&mut f)]);
+
+        assert_eq!(end, Instant::new(5 + 2 + 1));
+    }
+}
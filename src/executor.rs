@@ -1,20 +1,46 @@
 use core::cell::Cell;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::{Pin, pin};
 use core::sync::atomic::Ordering;
-use core::task::{Context, Poll, Waker};
+use core::task::{Context, Poll};
 
 use futures::FutureExt;
 use futures::task::LocalFutureObj;
 use portable_atomic::AtomicBool;
 
-use crate::time::Instant;
-use crate::waker::WakerInfo;
+use crate::cancellation::CancellationToken;
+use crate::instrument::{Instrument, WakeSource};
+use crate::replay::Replayer;
+use crate::sim::Rng;
+use crate::time::{Duration, Instant};
+use crate::waker::Registration;
 
 pub trait Environment: core::fmt::Debug {
     /// Sleeps until `event` becomes true or `tick` is reached.
     /// Early return is okay but causes performance overhead.
     fn wait_for_event_with_deadline(&self, event: &AtomicBool, tick: Option<Instant>);
+    /// Sleeps like `wait_for_event_with_deadline`, with `remaining` ticks left until `tick`.
+    /// Override to choose between light and deep sleep states: deep sleep usually
+    /// pays off only when `remaining` is long or `None` (no deadline at all).
+    fn idle(&self, event: &AtomicBool, tick: Option<Instant>, remaining: Option<Duration>) {
+        let _ = remaining;
+        self.wait_for_event_with_deadline(event, tick);
+    }
     /// Gets current tick count.
     fn ticks(&self) -> Instant;
+    /// Returns index of the calling core, for running an executor per core.
+    /// Wakers called on another core than their executor's one call `signal_core`
+    /// after raising its wakeup event. Must be callable from all cores and interrupts,
+    /// as must be `signal_core`.
+    fn core_id(&self) -> usize {
+        0
+    }
+    /// Interrupts `idle` on `core` after its wakeup event was raised from another core,
+    /// e.g. by writing to the inter-core FIFO or executing `SEV`.
+    fn signal_core(&self, core: usize) {
+        let _ = core;
+    }
 }
 
 pub(crate) trait Executor: core::fmt::Debug {
@@ -24,16 +50,24 @@ pub(crate) trait Executor: core::fmt::Debug {
     fn wakeup_task_at(&self, task_index: usize, time: Instant) -> Poll<()>;
     /// Mark task as ready to run
     fn set_task_runnable(&self, task_index: usize);
+    /// Mark all unfinished tasks as ready to run
+    fn wake_all_tasks(&self);
+    /// Cancel the root token and stop running tasks after `grace` period
+    fn shutdown(&self, grace: Duration);
+    /// Returns metadata of an unfinished task
+    fn task_metadata(&self, task_index: usize) -> TaskMetadata;
+    /// Takes one unit of the running task's coop budget, returns false if it's exhausted
+    fn consume_budget(&self) -> bool;
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum TaskState {
+pub(crate) enum TaskState {
     Runnable,
     Waiting(Option<Instant>),
 }
 
 impl TaskState {
-    fn is_runnable(&self, tick: Instant) -> bool {
+    pub(crate) fn is_runnable(&self, tick: Instant) -> bool {
         match self {
             TaskState::Runnable => true,
             TaskState::Waiting(Some(expected_tick)) => *expected_tick <= tick,
@@ -42,14 +76,173 @@ impl TaskState {
     }
 }
 
+/// Runtime statistics of a task, measured in environment ticks.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskStats {
+    /// Number of times the task was polled.
+    pub polls: u32,
+    /// Number of times the task was woken by a waker or a timer.
+    pub wakes: u32,
+    /// Total time spent inside `poll`.
+    pub poll_time: Duration,
+    /// Longest single `poll`.
+    pub max_poll_time: Duration,
+    /// Total time the task was runnable but waited for other tasks to be polled.
+    pub runnable_time: Duration,
+    /// Total time the task was waiting for a wakeup.
+    pub sleep_time: Duration,
+}
+
+/// Identity of a task, reported in diagnostics.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskMetadata {
+    /// Position of the task in the array passed to the executor.
+    pub index: usize,
+    pub name: Option<&'static str>,
+    /// Arbitrary user-defined value.
+    pub tag: Option<u32>,
+    /// Higher priority tasks run first with `SchedulingPolicy::Priority`.
+    pub priority: u8,
+}
+
+/// Future to run on the executor, with optional metadata.
+#[derive(Debug)]
+pub struct Task<'f> {
+    future: LocalFutureObj<'f, ()>,
+    name: Option<&'static str>,
+    tag: Option<u32>,
+    priority: u8,
+}
+
+impl<'f> Task<'f> {
+    pub fn new(future: LocalFutureObj<'f, ()>) -> Self {
+        Self {
+            future,
+            name: None,
+            tag: None,
+            priority: 0,
+        }
+    }
+
+    /// Sets task name shown in diagnostics.
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Attaches user-defined tag.
+    pub fn with_tag(mut self, tag: u32) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Sets priority for `SchedulingPolicy::Priority`, default is 0.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn metadata(&self, index: usize) -> TaskMetadata {
+        TaskMetadata {
+            index,
+            name: self.name,
+            tag: self.tag,
+            priority: self.priority,
+        }
+    }
+}
+
+impl<'f> From<LocalFutureObj<'f, ()>> for Task<'f> {
+    fn from(future: LocalFutureObj<'f, ()>) -> Self {
+        Self::new(future)
+    }
+}
+
 #[derive(Debug)]
 struct TaskInfo {
-    waker: WakerInfo,
+    metadata: TaskMetadata,
     state: Cell<TaskState>,
+    stats: Cell<TaskStats>,
+    // When the task last became runnable or started waiting, None while it's polled.
+    state_since: Cell<Option<Instant>>,
+}
+
+impl TaskInfo {
+    fn new(metadata: TaskMetadata, now: Instant) -> Self {
+        Self {
+            metadata,
+            state: Cell::new(TaskState::Runnable),
+            stats: Cell::new(TaskStats::default()),
+            state_since: Cell::new(Some(now)),
+        }
+    }
+
+    fn update_stats(&self, f: impl FnOnce(&mut TaskStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    // Accounts waiting time ended by a wakeup at `tick`.
+    fn record_wake(&self, tick: Instant) {
+        if let Some(since) = self.state_since.get() {
+            self.update_stats(|stats| {
+                stats.wakes = stats.wakes.wrapping_add(1);
+                stats.sleep_time = stats.sleep_time + (tick - since);
+            });
+            self.state_since.set(Some(tick));
+        }
+    }
+
+    fn record_poll_start(&self, now: Instant) {
+        if let TaskState::Waiting(Some(deadline)) = self.state.get() {
+            // Woken by timer.
+            self.record_wake(deadline);
+        }
+
+        if let Some(since) = self.state_since.take() {
+            self.update_stats(|stats| stats.runnable_time = stats.runnable_time + (now - since));
+        }
+    }
+
+    fn record_poll_end(&self, start: Instant, end: Instant) {
+        let poll_time = end - start;
+        self.update_stats(|stats| {
+            stats.polls = stats.polls.wrapping_add(1);
+            stats.poll_time = stats.poll_time + poll_time;
+            stats.max_poll_time = stats.max_poll_time.max(poll_time);
+        });
+        self.state_since.set(Some(end));
+    }
+}
+
+/// Callback receiving the task that exceeded poll budget and its poll duration.
+pub type LongPollHandler = fn(&TaskMetadata, Duration);
+
+/// Order in which runnable tasks are polled on each executor iteration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SchedulingPolicy {
+    /// Tasks are polled by increasing index.
+    IndexOrder,
+    /// Each iteration starts one task further than the previous one,
+    /// so low-index tasks don't always run first.
+    RoundRobin,
+    /// Tasks with higher `Task::with_priority` are polled first,
+    /// tasks of equal priority take turns like in `RoundRobin`.
+    Priority,
+    /// Tasks are polled in pseudo-random order derived from `seed`, to explore
+    /// different interleavings in simulation.
+    Random { seed: u64 },
 }
 
+/// Outcome of polling the tasks, telling the caller what to do next.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum RunResult {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RunResult {
     /// Run next iteration immediately
     RunAgain,
     /// Wait for wakeup event or specified time
@@ -61,7 +254,7 @@ enum RunResult {
 }
 
 impl RunResult {
-    fn from_task_state(state: Option<TaskState>) -> Self {
+    pub(crate) fn from_task_state(state: Option<TaskState>) -> Self {
         match state {
             Some(TaskState::Runnable) => RunResult::RunAgain,
             Some(TaskState::Waiting(Some(tick))) => RunResult::WaitForTick(tick),
@@ -71,99 +264,564 @@ impl RunResult {
     }
 }
 
+/// Executor polling up to `N` tasks on the current core.
+///
+/// On multi-core parts, run one executor per core with an environment implementing
+/// `Environment::core_id` and `Environment::signal_core`. Tasks may then be woken from
+/// the other core, e.g. by posting to a `sync::Mailbox` they wait on.
 #[derive(Debug)]
-pub struct LocalExecutor<'a, const N: usize> {
+pub struct LocalExecutor<'a, const N: usize, I: Instrument = ()> {
+    // Unlinked first on drop, so wakers can't reach the executor while it's torn down.
+    registration: Registration,
     env: &'a dyn Environment,
+    instrument: I,
+    // Futures of unfinished tasks. Declared before `tasks`, so they are dropped
+    // while the wakers they may have registered still point at live task slots.
+    futures: [Option<Task<'a>>; N],
     // Space for tasks to run.
     tasks: [Option<TaskInfo>; N],
+    // Future passed to `run_until`, uses task index N.
+    main_task: Option<TaskInfo>,
     wakeup_event: AtomicBool,
+    // Core running the executor, see `Environment::core_id`.
+    core: usize,
+    // Tasks woken by wakers since they were last polled. Wakers only raise flags,
+    // so they can be called from interrupts and other cores.
+    wakeups: [AtomicBool; N],
+    main_wakeup: AtomicBool,
+    policy: SchedulingPolicy,
+    // Shuffles tasks for `SchedulingPolicy::Random`.
+    rng: Rng,
+    // Iteration count, rotating tasks with `RoundRobin` and `Priority` policies.
+    rotation: usize,
+    // Overrides `policy` to poll tasks in recorded order.
+    replay: Option<&'a Replayer<'a>>,
+    // Deadlines closer than this are busy-waited instead of sleeping.
+    min_sleep: Duration,
+    // Polls longer than budget are reported to the callback.
+    poll_budget: Option<(Duration, LongPollHandler)>,
+    // Operations a task may do in a single poll before being forced to yield.
+    coop_budget: Option<u32>,
+    budget_left: Cell<u32>,
+    // Cancelled when shutdown is requested.
+    root_token: Option<&'a CancellationToken<'a>>,
+    // Tasks still running at this tick are dropped.
+    shutdown_deadline: Cell<Option<Instant>>,
+    started: bool,
+    // Registry links to the executor, it must not move once tasks are started.
+    _pinned: PhantomPinned,
 }
 
 impl<'a, const N: usize> LocalExecutor<'a, N> {
     pub fn new(env: &'a dyn Environment) -> Self {
+        const { assert!(N < crate::waker::MAX_TASKS, "too many tasks") };
+
         Self {
+            registration: Registration::new(),
             env,
+            instrument: (),
+            futures: [const { None }; N],
             tasks: [const { None }; N],
+            main_task: None,
             wakeup_event: AtomicBool::new(false),
+            core: env.core_id(),
+            wakeups: [const { AtomicBool::new(false) }; N],
+            main_wakeup: AtomicBool::new(false),
+            policy: SchedulingPolicy::IndexOrder,
+            rng: Rng::new(0),
+            rotation: 0,
+            replay: None,
+            min_sleep: Duration::new(0),
+            poll_budget: None,
+            coop_budget: None,
+            budget_left: Cell::new(0),
+            root_token: None,
+            shutdown_deadline: Cell::new(None),
+            started: false,
+            _pinned: PhantomPinned,
+        }
+    }
+}
+
+impl<'a, const N: usize, I: Instrument> LocalExecutor<'a, N, I> {
+    /// Reports scheduler events to `instrument`.
+    pub fn with_instrument<J: Instrument>(self, instrument: J) -> LocalExecutor<'a, N, J> {
+        LocalExecutor {
+            registration: self.registration,
+            env: self.env,
+            instrument,
+            futures: self.futures,
+            tasks: self.tasks,
+            main_task: self.main_task,
+            wakeup_event: self.wakeup_event,
+            core: self.core,
+            wakeups: self.wakeups,
+            main_wakeup: self.main_wakeup,
+            policy: self.policy,
+            rng: self.rng,
+            rotation: self.rotation,
+            replay: self.replay,
+            min_sleep: self.min_sleep,
+            poll_budget: self.poll_budget,
+            coop_budget: self.coop_budget,
+            budget_left: self.budget_left,
+            root_token: self.root_token,
+            shutdown_deadline: self.shutdown_deadline,
+            started: self.started,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Sets tasks to run. The executor owns them, so they can't outlive it.
+    pub fn with_tasks<'f: 'a>(mut self, tasks: [impl Into<Task<'f>>; N]) -> Self {
+        self.futures = tasks.map(|task| Some(task.into()));
+        self
+    }
+
+    /// Sets order of polling runnable tasks.
+    pub fn with_policy(mut self, policy: SchedulingPolicy) -> Self {
+        self.policy = policy;
+        if let SchedulingPolicy::Random { seed } = policy {
+            self.rng = Rng::new(seed);
+        }
+        self
+    }
+
+    /// Replays recording of `replay::Recorder`: time comes from the recording and each
+    /// iteration polls only the recorded task. Panics when the run diverges from it.
+    pub fn with_replay(
+        mut self,
+        replayer: &'a Replayer<'a>,
+    ) -> LocalExecutor<'a, N, &'a Replayer<'a>> {
+        self.env = replayer;
+        self.core = replayer.core_id();
+        self.replay = Some(replayer);
+        self.with_instrument(replayer)
+    }
+
+    /// Spins instead of calling `Environment::idle` when the next deadline is less
+    /// than `min_sleep` ticks away, for hardware where sleeping costs more than that.
+    pub fn with_min_sleep(mut self, min_sleep: Duration) -> Self {
+        self.min_sleep = min_sleep;
+        self
+    }
+
+    /// Calls `on_long_poll` with task metadata and poll duration whenever a task
+    /// keeps executor busy for longer than `budget` ticks without yielding.
+    pub fn with_poll_budget(mut self, budget: Duration, on_long_poll: LongPollHandler) -> Self {
+        self.poll_budget = Some((budget, on_long_poll));
+        self
+    }
+
+    /// Makes crate primitives, such as `Mailbox::read` and `now`, return `Pending` and
+    /// reschedule the task after `budget` operations in a single poll, so a task that
+    /// never waits doesn't keep other tasks from running.
+    /// Budget of 0 is raised to 1, otherwise no task could ever make progress.
+    pub fn with_coop_budget(mut self, budget: u32) -> Self {
+        self.coop_budget = Some(budget.max(1));
+        self
+    }
+
+    /// Sets token to cancel on `shutdown()`. Tasks should watch it or its children.
+    pub fn with_cancellation(mut self, root_token: &'a CancellationToken<'a>) -> Self {
+        self.root_token = Some(root_token);
+        self
+    }
+
+    /// Cancels the root token and lets tasks finish for `grace` ticks.
+    /// After that, unfinished tasks are abandoned and `run` returns, dropping their futures.
+    /// `run_until` main future is not abandoned.
+    /// Tasks can request shutdown with `crate::shutdown()`.
+    pub fn shutdown(&self, grace: Duration) {
+        if self.shutdown_deadline.get().is_none() {
+            self.shutdown_deadline.set(Some(self.env.ticks() + grace));
+        }
+
+        if let Some(token) = self.root_token {
+            token.cancel();
         }
     }
 
     // Run all futures to completion.
-    pub fn run(mut self, mut futures: [LocalFutureObj<'_, ()>; N]) {
-        for index in 0..N {
-            self.tasks[index] = Some(TaskInfo {
-                waker: WakerInfo::new(index, unsafe {
-                    core::mem::transmute::<*const dyn Executor, *const dyn Executor>(
-                        &self as *const dyn Executor,
-                    )
-                }),
-                state: Cell::new(TaskState::Runnable),
-            });
+    pub fn run<'f: 'a>(self, futures: [impl Into<Task<'f>>; N]) {
+        let mut this = pin!(self.with_tasks(futures));
+
+        loop {
+            match this.as_mut().run_until_stalled() {
+                RunResult::NoMoreTasks => break,
+                result => this.wait(result),
+            }
         }
+    }
+
+    /// Runs tasks until `main` completes and returns its output.
+    /// Unfinished tasks stay in the executor and are resumed by the next call.
+    pub fn run_until<T>(self: Pin<&mut Self>, main: impl Future<Output = T>) -> T {
+        let this = self.project();
+        let mut output = None;
+
+        {
+            let mut main = pin!(async { output = Some(main.await) });
+            let mut main = Task::new(LocalFutureObj::new(main.as_mut())).with_name("main");
 
+            this.start();
+            this.main_task = Some(TaskInfo::new(main.metadata(N), this.env.ticks()));
+
+            loop {
+                let result = this.run_once(Some(&mut main));
+                if this.main_task.is_none() {
+                    break;
+                }
+                this.wait(result);
+            }
+        }
+
+        output.expect("main task finished without output")
+    }
+
+    /// Runs tasks until none of them can make progress without waiting,
+    /// returning what the executor would wait for.
+    pub fn run_until_stalled(mut self: Pin<&mut Self>) -> RunResult {
         loop {
-            match self.run_once(&mut futures) {
+            match self.as_mut().poll_once() {
                 RunResult::RunAgain => continue,
-                RunResult::WaitForTick(tick) => self
-                    .env
-                    .wait_for_event_with_deadline(&self.wakeup_event, Some(tick)),
-                RunResult::WaitForEvent => self
-                    .env
-                    .wait_for_event_with_deadline(&self.wakeup_event, None),
-                RunResult::NoMoreTasks => break,
+                result => return result,
+            }
+        }
+    }
+
+    /// Polls every runnable task once, for embedding the executor into an external loop.
+    /// The caller decides how to wait according to the result; waking tasks sets
+    /// `wakeup_event()` in addition to any environment-specific signalling.
+    pub fn poll_once(self: Pin<&mut Self>) -> RunResult {
+        let this = self.project();
+        this.start();
+        this.run_once(None)
+    }
+
+    /// Returns statistics of an unfinished task.
+    pub fn task_stats(&self, task_index: usize) -> Option<TaskStats> {
+        self.tasks
+            .get(task_index)?
+            .as_ref()
+            .map(|task| task.stats.get())
+    }
+
+    /// Flag raised when a task is woken, to be passed to the host's own wait routine.
+    pub fn wakeup_event(&self) -> &AtomicBool {
+        &self.wakeup_event
+    }
+
+    /// Runs tasks for `duration` ticks or until they are all finished.
+    /// Returns the state of the last iteration.
+    pub fn run_for(self: Pin<&mut Self>, duration: Duration) -> RunResult {
+        let this = self.project();
+        let deadline = this.env.ticks() + duration;
+        this.start();
+
+        loop {
+            let result = this.run_once(None);
+            if result == RunResult::NoMoreTasks || this.env.ticks() >= deadline {
+                return result;
+            }
+
+            this.wait(result.min(RunResult::WaitForTick(deadline)));
+        }
+    }
+
+    fn project(self: Pin<&mut Self>) -> &mut Self {
+        // SAFETY: executor never moves out of its own fields.
+        unsafe { self.get_unchecked_mut() }
+    }
+
+    // Binds tasks to the executor on first run.
+    fn start(&mut self) {
+        if !self.started {
+            self.core = self.env.core_id();
+            let executor: &dyn Executor = self;
+            // SAFETY: executor is pinned, and registration is dropped before other fields.
+            unsafe { self.registration.register(executor) };
+
+            for index in 0..N {
+                if let Some(task) = &self.futures[index] {
+                    let now = self.env.ticks();
+                    self.tasks[index] = Some(TaskInfo::new(task.metadata(index), now));
+                }
             }
+            self.started = true;
+        }
+    }
+
+    fn wait(&self, result: RunResult) {
+        match result {
+            RunResult::WaitForTick(tick) => self.wait_for_tick(tick),
+            RunResult::WaitForEvent => self.idle(None, None),
+            RunResult::RunAgain | RunResult::NoMoreTasks => {}
+        }
+    }
+
+    fn wait_for_tick(&self, tick: Instant) {
+        let remaining = tick - self.env.ticks();
+
+        if remaining < self.min_sleep {
+            // Sleeping costs more than it saves, busy-wait for the deadline instead.
+            while !self.wakeup_event.load(Ordering::Acquire) && self.env.ticks() < tick {
+                core::hint::spin_loop();
+            }
+        } else {
+            self.idle(Some(tick), Some(remaining));
+        }
+    }
+
+    fn idle(&self, tick: Option<Instant>, remaining: Option<Duration>) {
+        if I::ENABLED {
+            self.instrument.idle_enter(tick, self.env.ticks());
+        }
+
+        self.env.idle(&self.wakeup_event, tick, remaining);
+
+        if I::ENABLED {
+            self.instrument.idle_exit(self.env.ticks());
         }
     }
 
     // Polls all tasks once
-    fn run_once(&mut self, futures: &mut [LocalFutureObj<'_, ()>; N]) -> RunResult {
+    fn run_once(&mut self, main: Option<&mut Task<'_>>) -> RunResult {
         // Clear wakeup flag, it already activated this loop.
         self.wakeup_event.store(false, Ordering::Release);
+        self.apply_wakeups();
+
+        let mut shutdown_deadline = self.shutdown_deadline.get();
+        if let Some(deadline) = shutdown_deadline
+            && self.env.ticks() >= deadline
+        {
+            // Grace period is over, drop tasks that didn't finish.
+            self.futures.iter_mut().for_each(|task| *task = None);
+            self.tasks.iter_mut().for_each(|task| *task = None);
+            // Deadline is in the past, `run_until` main future mustn't busy-wait for it.
+            shutdown_deadline = None;
+        }
+
+        let main_result = main
+            .map(|task| self.run_task(N, &mut task.future))
+            .unwrap_or(RunResult::NoMoreTasks);
 
-        futures
-            .iter_mut()
+        let (order, count) = self.polling_order();
+        let result = order
+            .into_iter()
             .enumerate()
-            .map(|(task_index, future)| self.run_task(task_index, future))
-            .min()
-            .unwrap_or(RunResult::NoMoreTasks)
+            .map(|(position, task_index)| {
+                if position >= count {
+                    return RunResult::from_task_state(
+                        self.tasks[task_index].as_ref().map(|task| task.state.get()),
+                    );
+                }
+
+                // Moved out while polled, finished tasks are dropped here.
+                let Some(mut task) = self.futures[task_index].take() else {
+                    return RunResult::NoMoreTasks;
+                };
+                let result = self.run_task(task_index, &mut task.future);
+                if result != RunResult::NoMoreTasks {
+                    self.futures[task_index] = Some(task);
+                }
+                result
+            })
+            .fold(main_result, RunResult::min);
+
+        match shutdown_deadline {
+            Some(deadline) if result != RunResult::NoMoreTasks => {
+                result.min(RunResult::WaitForTick(deadline))
+            }
+            _ => result,
+        }
+    }
+
+    // Returns order of tasks and how many of them to poll.
+    fn polling_order(&mut self) -> ([usize; N], usize) {
+        let mut order: [usize; N] = core::array::from_fn(|task_index| task_index);
+
+        if let Some(replayer) = self.replay {
+            let task_index = replayer.next_task();
+            if task_index >= N {
+                panic!("replay diverged: recorded task {task_index} doesn't exist");
+            }
+            order.swap(0, task_index);
+            return (order, 1);
+        }
+
+        match self.policy {
+            SchedulingPolicy::IndexOrder => {}
+            SchedulingPolicy::RoundRobin => self.rotate(&mut order),
+            SchedulingPolicy::Priority => {
+                // Finished tasks go last, so they don't take turns of runnable ones.
+                let priority = |task_index: usize| {
+                    self.tasks[task_index]
+                        .as_ref()
+                        .map(|task| task.metadata.priority)
+                };
+                // Insertion sort is stable and doesn't need an allocator.
+                for sorted in 1..N {
+                    let mut position = sorted;
+                    while position > 0 && priority(order[position - 1]) < priority(order[position])
+                    {
+                        order.swap(position - 1, position);
+                        position -= 1;
+                    }
+                }
+
+                // Tasks of equal priority take turns within their class.
+                let mut start = 0;
+                while start < N {
+                    let class = priority(order[start]);
+                    let len = order[start..]
+                        .iter()
+                        .take_while(|&&task_index| priority(task_index) == class)
+                        .count();
+                    order[start..start + len].rotate_left(self.rotation % len);
+                    start += len;
+                }
+                self.rotation = self.rotation.wrapping_add(1);
+            }
+            SchedulingPolicy::Random { .. } => self.rng.shuffle(&mut order),
+        }
+
+        (order, N)
+    }
+
+    fn rotate(&mut self, order: &mut [usize; N]) {
+        if N > 0 {
+            order.rotate_left(self.rotation);
+            self.rotation = (self.rotation + 1) % N;
+        }
     }
 
     fn run_task(&mut self, task_index: usize, future: &mut LocalFutureObj<'_, ()>) -> RunResult {
-        let t = &mut self.tasks[task_index];
+        let env = self.env;
+        let now = env.ticks();
+        // Borrow fields separately, task slot is borrowed mutably.
+        let instrument = &self.instrument;
+        let poll_budget = self.poll_budget;
+        let coop_budget = self.coop_budget;
+        let budget_left = &self.budget_left;
+        let (t, wakeup) = if task_index == N {
+            (&mut self.main_task, &self.main_wakeup)
+        } else {
+            (&mut self.tasks[task_index], &self.wakeups[task_index])
+        };
 
         if let Some(task) = t
-            && task.state.get().is_runnable(self.env.ticks())
+            && take_flag(wakeup)
         {
-            let waker = unsafe { Waker::from_raw(task.waker.to_raw_waker()) };
-            let mut context = Context::from_waker(&waker);
+            Self::wake_task(instrument, task, now);
+        }
 
+        if let Some(task) = t
+            && task.state.get().is_runnable(now)
+        {
+            let metadata = task.metadata;
+            if let TaskState::Waiting(Some(deadline)) = task.state.get() {
+                instrument.task_woken(&metadata, WakeSource::Timer, deadline);
+                #[cfg(feature = "defmt")]
+                defmt::trace!("task {} woken by timer at {}", metadata, deadline);
+            }
+            task.record_poll_start(now);
+            instrument.poll_start(&metadata, now);
             // Let sleep and yield futures update this field.
             task.state.set(TaskState::Waiting(None));
+            budget_left.set(coop_budget.unwrap_or(u32::MAX));
 
-            if future.poll_unpin(&mut context).is_ready() {
+            let poll = self
+                .registration
+                .poll(task_index, |context| future.poll_unpin(context));
+            let end = env.ticks();
+            if take_flag(wakeup) {
+                // Woken while polled, e.g. by `yield_once`.
+                Self::wake_task(instrument, task, end);
+            }
+            task.record_poll_end(now, end);
+            instrument.poll_end(&metadata, end);
+
+            if let Some((budget, on_long_poll)) = poll_budget
+                && end - now > budget
+            {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("task {} polled for {}", metadata, end - now);
+                on_long_poll(&metadata, end - now);
+            }
+
+            if poll.is_ready() {
                 // Task finished
                 *t = None;
+                instrument.task_finished(&metadata, end);
+                #[cfg(feature = "defmt")]
+                defmt::trace!("task {} finished", metadata);
+            } else if let TaskState::Waiting(deadline) = task.state.get() {
+                instrument.task_sleep(&metadata, deadline, end);
+                #[cfg(feature = "defmt")]
+                defmt::trace!("task {} sleeps until {}", metadata, deadline);
             }
         }
 
         RunResult::from_task_state(t.as_ref().map(|task| task.state.get()))
     }
+
+    fn task(&self, task_index: usize) -> Option<&TaskInfo> {
+        debug_assert!(task_index <= N);
+
+        if task_index == N {
+            self.main_task.as_ref()
+        } else {
+            self.tasks[task_index].as_ref()
+        }
+    }
+
+    fn wakeup(&self, task_index: usize) -> &AtomicBool {
+        if task_index == N {
+            &self.main_wakeup
+        } else {
+            &self.wakeups[task_index]
+        }
+    }
+
+    // Applies wakeups that happened while the executor was idle.
+    fn apply_wakeups(&self) {
+        let mut now = None;
+        for task_index in 0..=N {
+            if self.wakeup(task_index).load(Ordering::Relaxed) {
+                let now = *now.get_or_insert_with(|| self.env.ticks());
+                if take_flag(self.wakeup(task_index))
+                    && let Some(task) = self.task(task_index)
+                {
+                    Self::wake_task(&self.instrument, task, now);
+                }
+            }
+        }
+    }
+
+    // Marks task runnable, updating its stats and reporting the wakeup.
+    fn wake_task(instrument: &I, task: &TaskInfo, now: Instant) {
+        if task.state.get() != TaskState::Runnable {
+            task.record_wake(now);
+            instrument.task_woken(&task.metadata, WakeSource::Waker, now);
+            #[cfg(feature = "defmt")]
+            defmt::trace!("task {} woken at {}", task.metadata, now);
+        }
+        task.state.set(TaskState::Runnable);
+    }
 }
 
-impl<'a, const N: usize> Executor for LocalExecutor<'a, N> {
+impl<'a, const N: usize, I: Instrument> Executor for LocalExecutor<'a, N, I> {
     fn current_time(&self) -> Instant {
         self.env.ticks()
     }
 
     fn wakeup_task_at(&self, task_index: usize, time: Instant) -> Poll<()> {
-        debug_assert!(task_index < N);
-
         if self.env.ticks() >= time {
             Poll::Ready(())
         } else {
             // This function is supposed to be called only for currently running task.
-            self.tasks[task_index]
-                .as_ref()
+            self.task(task_index)
                 .expect("wakeup_task_at() called for finished task")
                 .state
                 .update(|state| {
@@ -180,22 +838,124 @@ impl<'a, const N: usize> Executor for LocalExecutor<'a, N> {
     }
 
     fn set_task_runnable(&self, task_index: usize) {
-        debug_assert!(task_index < N);
-        self.tasks[task_index]
-            .as_ref()
-            .expect("set_task_runnable() called for finished task")
-            .state
-            .set(TaskState::Runnable);
+        if task_index > N {
+            // Waker of another executor, which had the same id long ago.
+            return;
+        }
+
+        // Task state is updated by the executor when it polls the task. Wakeups of
+        // finished tasks are ignored, abandoned futures may still have wakers registered.
+        self.wakeup(task_index).store(true, Ordering::Release);
+        self.wakeup_event.store(true, Ordering::Release);
+
+        if self.env.core_id() != self.core {
+            self.env.signal_core(self.core);
+        }
+    }
+
+    fn wake_all_tasks(&self) {
+        for task_index in 0..=N {
+            self.wakeup(task_index).store(true, Ordering::Release);
+        }
 
         self.wakeup_event.store(true, Ordering::Release);
     }
+
+    fn shutdown(&self, grace: Duration) {
+        LocalExecutor::shutdown(self, grace);
+    }
+
+    fn task_metadata(&self, task_index: usize) -> TaskMetadata {
+        self.task(task_index)
+            .expect("task_metadata() called for finished task")
+            .metadata
+    }
+
+    fn consume_budget(&self) -> bool {
+        match self.coop_budget {
+            None => true,
+            Some(_) => {
+                let left = self.budget_left.get();
+                self.budget_left.set(left.saturating_sub(1));
+                left > 0
+            }
+        }
+    }
+}
+
+/// Clears `flag`, returning whether it was set.
+///
+/// Critical section stands in for `swap`, which thumbv6m lacks. Wakers raise flags
+/// inside a critical section too, see `WakerInfo::with_executor`, so none is lost.
+pub(crate) fn take_flag(flag: &AtomicBool) -> bool {
+    flag.load(Ordering::Relaxed)
+        && critical_section::with(|_| {
+            let set = flag.load(Ordering::Acquire);
+            flag.store(false, Ordering::Relaxed);
+            set
+        })
+}
+
+/// Takes one unit of the coop budget. When it's exhausted, reschedules the task
+/// and returns `Pending`, so the caller yields before doing any work.
+pub(crate) fn consume_budget(cx: &mut Context<'_>) -> Poll<()> {
+    if crate::waker::with_current(cx.waker(), |executor, _| executor.consume_budget()) {
+        Poll::Ready(())
+    } else {
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use = "futures do nothing unless polled"]
+pub(crate) struct CurrentTask {}
+
+impl CurrentTask {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Future for CurrentTask {
+    type Output = TaskMetadata;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(crate::waker::with_current(
+            cx.waker(),
+            |executor, task_index| executor.task_metadata(task_index),
+        ))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use = "futures do nothing unless polled"]
+pub(crate) struct Shutdown {
+    grace: Duration,
+}
+
+impl Shutdown {
+    pub(crate) fn new(grace: Duration) -> Self {
+        Self { grace }
+    }
+}
+
+impl Future for Shutdown {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        crate::waker::with_current(cx.waker(), |executor, _| executor.shutdown(self.grace));
+        Poll::Ready(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use core::pin::pin;
 
-    use crate::test_utils::TestEnvironment;
+    use crate::mailbox::Mailbox;
+    use crate::test_utils::{CoreEnvironment, TestEnvironment};
+    use portable_atomic::AtomicUsize;
 
     use super::*;
     use futures::task::LocalFutureObj;
@@ -221,4 +981,570 @@ mod tests {
         // Check that task was actually run
         assert_eq!(v, 3);
     }
+
+    #[test]
+    fn test_min_sleep_spins() {
+        let env = TestEnvironment::new();
+        let mut f = pin!(crate::sleep(Duration::new(10)));
+        let fo = LocalFutureObj::new(&mut f);
+
+        LocalExecutor::new(&env)
+            .with_min_sleep(Duration::new(100))
+            .run([fo]);
+
+        assert_eq!(env.idle_count(), 0);
+    }
+
+    #[test]
+    fn test_long_sleep_idles() {
+        let env = TestEnvironment::new();
+        let mut f = pin!(crate::sleep(Duration::new(100)));
+        let fo = LocalFutureObj::new(&mut f);
+
+        LocalExecutor::new(&env)
+            .with_min_sleep(Duration::new(10))
+            .run([fo]);
+
+        assert!(env.idle_count() > 0);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            loop {
+                crate::sleep(Duration::new(5)).await;
+            }
+        });
+        let mut executor =
+            pin!(LocalExecutor::new(&env).with_tasks([Task::new(LocalFutureObj::new(&mut f))]));
+
+        let v = executor.as_mut().run_until(async {
+            crate::sleep(Duration::new(20)).await;
+            42
+        });
+
+        assert_eq!(v, 42);
+        assert!(env.current_tick() >= Instant::new(20));
+    }
+
+    #[test]
+    fn test_run_until_returns_with_blocked_task() {
+        // Simulation panics if the executor idles without a deadline.
+        let env = crate::sim::SimEnvironment::new();
+        let mbox = Mailbox::<i32>::new();
+        let mut f = pin!(async {
+            mbox.read().await.unwrap();
+        });
+        let mut executor = pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+        let v = executor.as_mut().run_until(async {
+            crate::sleep(Duration::new(10)).await;
+            42
+        });
+
+        assert_eq!(v, 42);
+        assert_eq!(env.ticks(), Instant::new(10));
+    }
+
+    #[test]
+    fn test_run_until_stalled() {
+        let mbox = Mailbox::<i32>::new();
+        let mut v = 0;
+        {
+            let env = TestEnvironment::new();
+            let mut f = pin!(async {
+                v = mbox.read().await.unwrap();
+            });
+            let mut executor =
+                pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+            assert_eq!(
+                executor.as_mut().run_until_stalled(),
+                RunResult::WaitForEvent
+            );
+
+            mbox.post(7);
+            assert_eq!(
+                executor.as_mut().run_until_stalled(),
+                RunResult::NoMoreTasks
+            );
+        }
+
+        assert_eq!(v, 7);
+    }
+
+    #[test]
+    fn test_waker_outlives_executor() {
+        let mbox = Mailbox::<i32>::new();
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            mbox.read().await.unwrap();
+        });
+
+        {
+            let mut executor =
+                pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+            assert_eq!(
+                executor.as_mut().run_until_stalled(),
+                RunResult::WaitForEvent
+            );
+        }
+
+        // Unfinished future still holds the waker of the dropped executor.
+        mbox.post(1);
+    }
+
+    #[test]
+    fn test_run_for() {
+        let count = Cell::new(0);
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            loop {
+                crate::sleep(Duration::new(10)).await;
+                count.update(|count| count + 1);
+            }
+        });
+        let mut executor = pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+        let result = executor.as_mut().run_for(Duration::new(100));
+
+        assert!(matches!(result, RunResult::WaitForTick(_)));
+        assert!(env.current_tick() >= Instant::new(100));
+        assert!((5..=10).contains(&count.get()));
+    }
+
+    #[test]
+    fn test_wake_applied_when_polled() {
+        let mbox = Mailbox::<i32>::new();
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            mbox.read().await.unwrap();
+            mbox.read().await.unwrap();
+        });
+        let mut executor = pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+        assert_eq!(executor.as_mut().poll_once(), RunResult::WaitForEvent);
+
+        // Waker may run in an interrupt, it only raises flags.
+        mbox.post(1);
+        assert!(executor.wakeup_event().load(Ordering::Acquire));
+        assert_eq!(executor.task_stats(0).unwrap().wakes, 0);
+
+        assert_eq!(executor.as_mut().poll_once(), RunResult::WaitForEvent);
+        assert_eq!(executor.task_stats(0).unwrap().wakes, 1);
+    }
+
+    #[test]
+    fn test_poll_once_from_host_loop() {
+        let mbox = Mailbox::<i32>::new();
+        let mut frames = 0;
+        let mut sum = 0;
+        {
+            let env = TestEnvironment::new();
+            let mut f = pin!(async {
+                for _ in 0..3 {
+                    sum += mbox.read().await.unwrap();
+                }
+            });
+            let mut executor =
+                pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+            while executor.as_mut().poll_once() != RunResult::NoMoreTasks {
+                frames += 1;
+                if frames % 2 == 0 {
+                    mbox.post(frames);
+                    assert!(executor.wakeup_event().load(Ordering::Acquire));
+                }
+            }
+        }
+
+        assert_eq!(sum, 2 + 4 + 6);
+        assert_eq!(frames, 6);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let token = CancellationToken::new();
+        let cleaned_up = Cell::new(false);
+        let stubborn_polls = Cell::new(0);
+
+        let env = TestEnvironment::new();
+        let mut f1 = pin!(async {
+            token.cancelled().await;
+            cleaned_up.set(true);
+        });
+        let mut f2 = pin!(async {
+            loop {
+                crate::sleep(Duration::new(10)).await;
+                stubborn_polls.update(|polls| polls + 1);
+            }
+        });
+        let mut f3 = pin!(async {
+            crate::sleep(Duration::new(5)).await;
+            crate::shutdown(Duration::new(50)).await;
+        });
+
+        // Returns despite f2 never finishing.
+        LocalExecutor::new(&env).with_cancellation(&token).run([
+            LocalFutureObj::new(&mut f1),
+            LocalFutureObj::new(&mut f2),
+            LocalFutureObj::new(&mut f3),
+        ]);
+
+        assert!(token.is_cancelled());
+        assert!(cleaned_up.get());
+        assert!(env.current_tick() >= Instant::new(55));
+        assert!(stubborn_polls.get() <= 6);
+    }
+
+    #[test]
+    fn test_sleep_after_grace_period() {
+        // Simulated time only advances when the executor idles.
+        let env = crate::sim::SimEnvironment::new();
+        let mbox = Mailbox::<i32>::new();
+        let mut f = pin!(async {
+            mbox.read().await.unwrap();
+        });
+        let mut executor = pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+        executor.as_mut().run_until(async {
+            crate::shutdown(Duration::new(5)).await;
+            // Next iteration waits for the grace period, the one after abandons the task.
+            crate::yield_once().await;
+            crate::sleep(Duration::new(100)).await;
+        });
+
+        assert_eq!(env.ticks(), Instant::new(100));
+    }
+
+    #[test]
+    fn test_wake_abandoned_task() {
+        let mbox = Mailbox::<i32>::new();
+        let received = Cell::new(None);
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            received.set(Some(mbox.read().await.unwrap()));
+        });
+        let mut executor = pin!(LocalExecutor::new(&env).with_tasks([LocalFutureObj::new(&mut f)]));
+
+        executor.as_mut().run_until(async {
+            crate::shutdown(Duration::new(5)).await;
+            crate::sleep(Duration::new(10)).await;
+            // Abandoned task's future is borrowed, so it still waits for the mailbox.
+            mbox.post(1);
+        });
+
+        assert_eq!(received.get(), None);
+    }
+
+    #[test]
+    fn test_task_stats() {
+        let env = TestEnvironment::new();
+        let mut f1 = pin!(async {
+            loop {
+                crate::sleep(Duration::new(100)).await;
+            }
+        });
+        let mut f2 = pin!(async {
+            for _ in 0..5 {
+                crate::yield_once().await;
+            }
+        });
+        let mut executor = pin!(
+            LocalExecutor::new(&env)
+                .with_tasks([LocalFutureObj::new(&mut f1), LocalFutureObj::new(&mut f2),])
+        );
+
+        executor.as_mut().run_for(Duration::new(250));
+
+        let sleeper = executor.task_stats(0).unwrap();
+        assert_eq!(sleeper.polls, 3);
+        assert_eq!(sleeper.wakes, 2);
+        assert!(sleeper.sleep_time >= Duration::new(180));
+        assert!(sleeper.max_poll_time <= sleeper.poll_time);
+        assert_eq!(executor.task_stats(1), None);
+    }
+
+    #[test]
+    fn test_instrument_events() {
+        #[derive(Debug, Default)]
+        struct Recorder(std::cell::RefCell<std::vec::Vec<&'static str>>);
+
+        impl Instrument for Recorder {
+            fn poll_start(&self, _task: &TaskMetadata, _tick: Instant) {
+                self.0.borrow_mut().push("poll");
+            }
+
+            fn task_woken(&self, _task: &TaskMetadata, source: WakeSource, _tick: Instant) {
+                self.0.borrow_mut().push(match source {
+                    WakeSource::Timer => "timer",
+                    WakeSource::Waker => "waker",
+                });
+            }
+
+            fn task_sleep(&self, _task: &TaskMetadata, deadline: Option<Instant>, _tick: Instant) {
+                assert!(deadline.is_some());
+                self.0.borrow_mut().push("sleep");
+            }
+
+            fn task_finished(&self, _task: &TaskMetadata, _tick: Instant) {
+                self.0.borrow_mut().push("finished");
+            }
+
+            fn idle_enter(&self, _deadline: Option<Instant>, _tick: Instant) {
+                self.0.borrow_mut().push("idle");
+            }
+        }
+
+        let recorder = Recorder::default();
+        let env = TestEnvironment::new();
+        let mut f = pin!(async {
+            crate::sleep(Duration::new(10)).await;
+            crate::yield_once().await;
+        });
+
+        LocalExecutor::new(&env)
+            .with_instrument(&recorder)
+            .run([LocalFutureObj::new(&mut f)]);
+
+        // Test environment returns from idle immediately, executor may idle several times.
+        let mut events = recorder.0.take();
+        events.dedup();
+        assert_eq!(
+            events,
+            [
+                "poll", "sleep", "idle", "timer", "poll", "waker", "poll", "finished"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poll_budget() {
+        static LONG_POLLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_long_poll(task: &TaskMetadata, duration: Duration) {
+            assert_eq!(task.index, 1);
+            assert_eq!(task.name, Some("busy"));
+            assert!(duration > Duration::new(10));
+            LONG_POLLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let env = TestEnvironment::new();
+        let mut f1 = pin!(async {
+            for _ in 0..5 {
+                crate::yield_once().await;
+            }
+        });
+        let mut f2 = pin!(async {
+            crate::yield_once().await;
+            // Busy loop, test environment clock advances on each read.
+            for _ in 0..20 {
+                env.ticks();
+            }
+        });
+
+        LocalExecutor::new(&env)
+            .with_poll_budget(Duration::new(10), on_long_poll)
+            .run([
+                Task::new(LocalFutureObj::new(&mut f1)),
+                Task::new(LocalFutureObj::new(&mut f2)).with_name("busy"),
+            ]);
+
+        assert_eq!(LONG_POLLS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_current_task() {
+        let env = TestEnvironment::new();
+        let mut seen = [None, None];
+        {
+            let (first, second) = seen.split_at_mut(1);
+            let mut f1 = pin!(async {
+                first[0] = Some(crate::current_task().await);
+            });
+            let mut f2 = pin!(async {
+                second[0] = Some(crate::current_task().await);
+            });
+
+            LocalExecutor::new(&env).run([
+                Task::new(LocalFutureObj::new(&mut f1)).with_name("first"),
+                Task::new(LocalFutureObj::new(&mut f2)).with_tag(7),
+            ]);
+        }
+
+        assert_eq!(
+            seen,
+            [
+                Some(TaskMetadata {
+                    index: 0,
+                    name: Some("first"),
+                    tag: None,
+                    priority: 0,
+                }),
+                Some(TaskMetadata {
+                    index: 1,
+                    name: None,
+                    tag: Some(7),
+                    priority: 0,
+                }),
+            ]
+        );
+    }
+
+    // Three tasks keep waking themselves, logging which one runs first in each iteration.
+    fn leaders(policy: SchedulingPolicy, priorities: [u8; 3]) -> std::vec::Vec<usize> {
+        let env = TestEnvironment::new();
+        let log = core::cell::RefCell::new(std::vec::Vec::new());
+        let polled = Cell::new(0);
+
+        let storm = |index: usize| {
+            let (log, polled) = (&log, &polled);
+            async move {
+                for _ in 0..6 {
+                    // Every third poll starts a new executor iteration.
+                    if polled.get() % 3 == 0 {
+                        log.borrow_mut().push(index);
+                    }
+                    polled.update(|polled| polled + 1);
+                    crate::yield_once().await;
+                }
+            }
+        };
+        let mut f0 = pin!(storm(0));
+        let mut f1 = pin!(storm(1));
+        let mut f2 = pin!(storm(2));
+
+        LocalExecutor::new(&env).with_policy(policy).run([
+            Task::new(LocalFutureObj::new(&mut f0)).with_priority(priorities[0]),
+            Task::new(LocalFutureObj::new(&mut f1)).with_priority(priorities[1]),
+            Task::new(LocalFutureObj::new(&mut f2)).with_priority(priorities[2]),
+        ]);
+
+        log.take()
+    }
+
+    #[test]
+    fn test_index_order_favours_first_task() {
+        assert_eq!(
+            leaders(SchedulingPolicy::IndexOrder, [0; 3]),
+            [0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_round_robin_rotates_leader() {
+        assert_eq!(
+            leaders(SchedulingPolicy::RoundRobin, [0; 3]),
+            [0, 1, 2, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_priority_order() {
+        assert_eq!(
+            leaders(SchedulingPolicy::Priority, [0, 0, 5]),
+            [2, 2, 2, 2, 2, 2]
+        );
+        // Equal priorities share the lead evenly.
+        assert_eq!(
+            leaders(SchedulingPolicy::Priority, [1, 1, 0]),
+            [0, 1, 0, 1, 0, 1]
+        );
+        assert_eq!(
+            leaders(SchedulingPolicy::Priority, [0, 1, 1]),
+            [1, 2, 1, 2, 1, 2]
+        );
+    }
+
+    // Task 0 reads a mailbox it keeps posting to, so the read is always ready.
+    fn progress_seen_by_other_task(coop_budget: Option<u32>) -> u32 {
+        let env = TestEnvironment::new();
+        let mbox = crate::mailbox::Mailbox::<u32>::new();
+        let reads = Cell::new(0);
+        let seen = Cell::new(None);
+
+        let mut greedy = pin!(async {
+            for value in 0..100 {
+                mbox.post(value);
+                mbox.read().await.unwrap();
+                reads.update(|reads| reads + 1);
+            }
+        });
+        let mut other = pin!(async {
+            seen.set(Some(reads.get()));
+        });
+
+        let executor = LocalExecutor::new(&env);
+        let executor = match coop_budget {
+            Some(budget) => executor.with_coop_budget(budget),
+            None => executor,
+        };
+        executor.run([
+            LocalFutureObj::new(&mut greedy),
+            LocalFutureObj::new(&mut other),
+        ]);
+
+        assert_eq!(reads.get(), 100);
+        seen.get().unwrap()
+    }
+
+    #[test]
+    fn test_coop_budget() {
+        assert_eq!(progress_seen_by_other_task(None), 100);
+        assert_eq!(progress_seen_by_other_task(Some(10)), 10);
+        assert_eq!(progress_seen_by_other_task(Some(0)), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_task_stats_serde() {
+        let stats = TaskStats {
+            polls: 3,
+            wakes: 2,
+            poll_time: Duration::new(30),
+            max_poll_time: Duration::new(15),
+            runnable_time: Duration::new(5),
+            sleep_time: Duration::new(100),
+        };
+
+        let json = serde_json::to_string(&stats).unwrap();
+        assert_eq!(
+            json,
+            r#"{"polls":3,"wakes":2,"poll_time":30,"max_poll_time":15,"runnable_time":5,"sleep_time":100}"#
+        );
+        assert_eq!(serde_json::from_str::<TaskStats>(&json).unwrap(), stats);
+    }
+
+    #[test]
+    fn wake_from_other_core() {
+        let env = CoreEnvironment::default();
+        let ping = crate::sync::mailbox::Mailbox::<u32>::new();
+        let pong = crate::sync::mailbox::Mailbox::<u32>::new();
+
+        std::thread::scope(|scope| {
+            for core in 0..2 {
+                let (env, ping, pong) = (&env, &ping, &pong);
+                scope.spawn(move || {
+                    env.enter(core);
+
+                    let mut task = pin!(async move {
+                        for round in 0..100 {
+                            if core == 0 {
+                                ping.post(round);
+                                assert_eq!(pong.read().await, Ok(round));
+                            } else {
+                                pong.post(ping.read().await.unwrap());
+                            }
+                        }
+                    });
+
+                    LocalExecutor::new(env).run([LocalFutureObj::new(&mut task)]);
+                });
+            }
+        });
+
+        // Executors sleep until signalled, so they only finish if the hook is called.
+        assert!(env.signals() > 0);
+    }
 }
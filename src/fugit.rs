@@ -0,0 +1,188 @@
+#![deny(unsafe_code)]
+
+use portable_atomic::AtomicBool;
+use thiserror::Error;
+
+use crate::executor::Environment;
+use crate::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Tick count doesn't fit into the target type.
+    #[error("tick count out of range")]
+    OutOfRange,
+}
+
+/// Monotonic timer counting `NOM / DENOM` second ticks, as provided by HAL crates.
+pub trait Monotonic<const NOM: u32, const DENOM: u32>: core::fmt::Debug {
+    /// Returns current time.
+    fn now(&self) -> ::fugit::Instant<u64, NOM, DENOM>;
+    /// Sleeps until `event` becomes true or `deadline` is reached,
+    /// see `Environment::wait_for_event_with_deadline`.
+    fn wait(&self, event: &AtomicBool, deadline: Option<::fugit::Instant<u64, NOM, DENOM>>);
+}
+
+/// Lets a `Monotonic` timer drive `LocalExecutor`, executor ticks are timer ticks.
+/// Fugit types convert to and from executor ticks through it, so they match the timer rate.
+#[derive(Debug)]
+pub struct MonotonicEnvironment<T, const NOM: u32, const DENOM: u32> {
+    timer: T,
+}
+
+impl<T: Monotonic<NOM, DENOM>, const NOM: u32, const DENOM: u32>
+    MonotonicEnvironment<T, NOM, DENOM>
+{
+    pub const fn new(timer: T) -> Self {
+        Self { timer }
+    }
+
+    pub fn timer(&self) -> &T {
+        &self.timer
+    }
+
+    /// Converts duration of any tick rate into executor ticks, rounding down.
+    /// Use `.into()` for 32-bit fugit durations.
+    pub fn duration<const D_NOM: u32, const D_DENOM: u32>(
+        &self,
+        duration: ::fugit::Duration<u64, D_NOM, D_DENOM>,
+    ) -> Result<Duration, Error> {
+        let duration = duration
+            .const_try_into::<NOM, DENOM>()
+            .ok_or(Error::OutOfRange)?;
+        i64::try_from(duration.ticks())
+            .map(Duration::new)
+            .map_err(|_| Error::OutOfRange)
+    }
+
+    /// Converts executor ticks into duration at the timer rate.
+    pub fn fugit_duration(
+        &self,
+        duration: Duration,
+    ) -> Result<::fugit::Duration<u64, NOM, DENOM>, Error> {
+        u64::try_from(duration.ticks())
+            .map(::fugit::Duration::<u64, NOM, DENOM>::from_ticks)
+            .map_err(|_| Error::OutOfRange)
+    }
+
+    /// Converts timer instant into executor ticks.
+    pub fn instant(&self, instant: ::fugit::Instant<u64, NOM, DENOM>) -> Result<Instant, Error> {
+        i64::try_from(instant.ticks())
+            .map(Instant::new)
+            .map_err(|_| Error::OutOfRange)
+    }
+
+    /// Converts executor ticks into timer instant.
+    pub fn fugit_instant(
+        &self,
+        instant: Instant,
+    ) -> Result<::fugit::Instant<u64, NOM, DENOM>, Error> {
+        u64::try_from(instant.ticks())
+            .map(::fugit::Instant::<u64, NOM, DENOM>::from_ticks)
+            .map_err(|_| Error::OutOfRange)
+    }
+}
+
+impl<T: Monotonic<NOM, DENOM>, const NOM: u32, const DENOM: u32> Environment
+    for MonotonicEnvironment<T, NOM, DENOM>
+{
+    fn wait_for_event_with_deadline(&self, event: &AtomicBool, tick: Option<Instant>) {
+        // Deadlines before the timer epoch are already due.
+        let deadline = tick.map(|tick| {
+            ::fugit::Instant::<u64, NOM, DENOM>::from_ticks(tick.ticks().max(0) as u64)
+        });
+        self.timer.wait(event, deadline);
+    }
+
+    fn ticks(&self) -> Instant {
+        // Timers don't run for 2^63 ticks, saturate instead of panicking just in case.
+        self.instant(self.timer.now()).unwrap_or(Instant::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::LocalExecutor;
+
+    type Millis = ::fugit::Instant<u64, 1, 1000>;
+
+    // Millisecond timer which jumps to the deadline when waiting.
+    #[derive(Debug, Default)]
+    struct TestTimer {
+        now: Cell<u64>,
+    }
+
+    impl Monotonic<1, 1000> for TestTimer {
+        fn now(&self) -> Millis {
+            Millis::from_ticks(self.now.get())
+        }
+
+        fn wait(&self, _event: &AtomicBool, deadline: Option<Millis>) {
+            if let Some(deadline) = deadline {
+                self.now.set(self.now.get().max(deadline.ticks()));
+            }
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let env = MonotonicEnvironment::new(TestTimer::default());
+
+        assert_eq!(env.instant(Millis::from_ticks(42)), Ok(Instant::new(42)));
+        assert_eq!(
+            env.fugit_instant(Instant::new(42)),
+            Ok(Millis::from_ticks(42))
+        );
+        assert_eq!(
+            env.instant(Millis::from_ticks(u64::MAX)),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(env.fugit_instant(Instant::new(-1)), Err(Error::OutOfRange));
+
+        // Durations of other rates are scaled to milliseconds.
+        assert_eq!(
+            env.duration(::fugit::SecsDurationU32::from_ticks(2).into()),
+            Ok(Duration::new(2000))
+        );
+        assert_eq!(
+            env.duration(::fugit::MicrosDurationU64::from_ticks(2500)),
+            Ok(Duration::new(2))
+        );
+        assert_eq!(
+            env.duration(::fugit::SecsDurationU64::from_ticks(u64::MAX)),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(
+            env.fugit_duration(Duration::new(5)),
+            Ok(::fugit::MillisDurationU64::from_ticks(5))
+        );
+        assert_eq!(
+            env.fugit_duration(Duration::new(-1)),
+            Err(Error::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn drives_executor() {
+        let env = MonotonicEnvironment::new(TestTimer::default());
+        let woke_at = Cell::new(None);
+        let delay = env
+            .duration(::fugit::MicrosDurationU64::from_ticks(250_000))
+            .unwrap();
+        let mut task = pin!(async {
+            crate::sleep(delay).await;
+            woke_at.set(Some(crate::now().await));
+        });
+
+        LocalExecutor::new(&env).run([LocalFutureObj::new(&mut task)]);
+
+        assert_eq!(woke_at.get(), Some(Instant::new(250)));
+        assert_eq!(env.timer().now(), Millis::from_ticks(250));
+    }
+}
@@ -0,0 +1,522 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::Ordering;
+use core::task::Poll;
+
+use critical_section::Mutex;
+use portable_atomic::AtomicBool;
+
+use crate::executor::{Environment, Executor, RunResult, TaskMetadata, TaskState, take_flag};
+use crate::time::{Duration, Instant};
+use crate::waker::Registration;
+
+type BoxedTask<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Executor keeping any number of boxed tasks, spawned before or while it runs.
+///
+/// Tasks use the same `sleep`, `now`, `yield_once` and mailboxes as with `LocalExecutor`,
+/// and may be woken from interrupt handlers or other cores the same way.
+pub struct HeapExecutor<'a> {
+    // Boxed, so its registration stays in place when the executor moves.
+    inner: Box<Inner<'a>>,
+}
+
+struct Inner<'a> {
+    // Unlinked first on drop, so wakers can't reach the executor while it's torn down.
+    registration: Registration,
+    env: &'a dyn Environment,
+    core: usize,
+    // Wakers refer to slots by index, so the vector may reallocate while they exist.
+    // Slots are never removed, so stale wakers refer to a free or reused slot.
+    slots: RefCell<Vec<Slot<'a>>>,
+    free: RefCell<Vec<usize>>,
+    // Wakeups by slot index, applied to slot states by `run_once`. Wakers may run in
+    // interrupt handlers or on other cores, so they only raise flags, and the vector
+    // grows inside a critical section.
+    wakeups: Mutex<RefCell<Vec<AtomicBool>>>,
+    wake_all: AtomicBool,
+    spawned: Rc<SpawnQueue<'a>>,
+    wakeup_event: AtomicBool,
+    shutdown_deadline: Cell<Option<Instant>>,
+}
+
+struct Slot<'a> {
+    state: Cell<TaskState>,
+    // Taken out while the task is polled, None if the slot is free.
+    future: Cell<Option<BoxedTask<'a>>>,
+    occupied: Cell<bool>,
+}
+
+struct SpawnQueue<'a> {
+    tasks: RefCell<Vec<BoxedTask<'a>>>,
+}
+
+/// Handle for spawning tasks onto `HeapExecutor`, can be moved into its tasks.
+#[derive(Clone)]
+pub struct Spawner<'a> {
+    queue: Rc<SpawnQueue<'a>>,
+}
+
+impl Debug for HeapExecutor<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl Debug for Inner<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HeapExecutor")
+            .field("env", &self.env)
+            .field("tasks", &self.task_count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Debug for Spawner<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Spawner").finish_non_exhaustive()
+    }
+}
+
+impl<'a> Spawner<'a> {
+    /// Adds task, it's polled on the next executor iteration.
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'a) {
+        self.queue.tasks.borrow_mut().push(Box::pin(future));
+    }
+}
+
+impl<'a> HeapExecutor<'a> {
+    pub fn new(env: &'a dyn Environment) -> Self {
+        let inner = Box::new(Inner {
+            registration: Registration::new(),
+            env,
+            core: env.core_id(),
+            slots: RefCell::new(Vec::new()),
+            free: RefCell::new(Vec::new()),
+            wakeups: Mutex::new(RefCell::new(Vec::new())),
+            wake_all: AtomicBool::new(false),
+            spawned: Rc::new(SpawnQueue {
+                tasks: RefCell::new(Vec::new()),
+            }),
+            wakeup_event: AtomicBool::new(false),
+            shutdown_deadline: Cell::new(None),
+        });
+        // SAFETY: `Inner` is boxed, and registration is dropped before other fields.
+        unsafe { inner.registration.register(&*inner) };
+
+        Self { inner }
+    }
+
+    /// Adds task, see `Spawner::spawn`.
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'a) {
+        self.spawner().spawn(future);
+    }
+
+    /// Returns handle for spawning tasks from other tasks.
+    pub fn spawner(&self) -> Spawner<'a> {
+        Spawner {
+            queue: self.inner.spawned.clone(),
+        }
+    }
+
+    /// Returns number of unfinished tasks.
+    pub fn task_count(&self) -> usize {
+        self.inner.task_count()
+    }
+
+    /// Runs tasks until all of them are finished.
+    pub fn run(&self) {
+        let inner = &*self.inner;
+
+        loop {
+            match inner.run_once() {
+                RunResult::NoMoreTasks => break,
+                RunResult::RunAgain => {}
+                RunResult::WaitForTick(tick) => {
+                    let remaining = tick - inner.env.ticks();
+                    inner
+                        .env
+                        .idle(&inner.wakeup_event, Some(tick), Some(remaining));
+                }
+                RunResult::WaitForEvent => inner.env.idle(&inner.wakeup_event, None, None),
+            }
+        }
+    }
+}
+
+impl Drop for HeapExecutor<'_> {
+    fn drop(&mut self) {
+        // Queued tasks may hold spawners, which keep the queue alive.
+        let queued = self.inner.spawned.tasks.take();
+        drop(queued);
+    }
+}
+
+impl<'a> Inner<'a> {
+    fn task_count(&self) -> usize {
+        let queued = self.spawned.tasks.borrow().len();
+        let running = self
+            .slots
+            .borrow()
+            .iter()
+            .filter(|slot| slot.occupied.get())
+            .count();
+
+        queued + running
+    }
+
+    fn run_once(&self) -> RunResult {
+        // Clear wakeup flag, it already activated this loop.
+        self.wakeup_event.store(false, Ordering::Release);
+
+        let mut shutdown_deadline = self.shutdown_deadline.get();
+        if let Some(deadline) = shutdown_deadline
+            && self.env.ticks() >= deadline
+        {
+            // Grace period is over, abandon tasks that didn't finish.
+            drop(self.spawned.tasks.take());
+            for (index, slot) in self.slots.borrow().iter().enumerate() {
+                self.release(index, slot);
+            }
+            // Deadline is in the past, tasks spawned later mustn't busy-wait for it.
+            shutdown_deadline = None;
+        }
+
+        self.adopt_spawned();
+        self.apply_wakeups();
+
+        let result = self
+            .slots
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| self.run_task(index, slot))
+            .fold(RunResult::NoMoreTasks, RunResult::min);
+
+        let result = if self.spawned.tasks.borrow().is_empty() {
+            result
+        } else {
+            RunResult::RunAgain
+        };
+
+        match shutdown_deadline {
+            Some(deadline) if result != RunResult::NoMoreTasks => {
+                result.min(RunResult::WaitForTick(deadline))
+            }
+            _ => result,
+        }
+    }
+
+    // Moves spawned tasks into free slots, adding slots if needed.
+    fn adopt_spawned(&self) {
+        let spawned = self.spawned.tasks.take();
+
+        for future in spawned {
+            let index = self.free.borrow_mut().pop().unwrap_or_else(|| {
+                let mut slots = self.slots.borrow_mut();
+                let index = slots.len();
+                assert!(index < crate::waker::MAX_TASKS, "too many tasks");
+                slots.push(Slot {
+                    state: Cell::new(TaskState::Runnable),
+                    future: Cell::new(None),
+                    occupied: Cell::new(false),
+                });
+                critical_section::with(|cs| {
+                    self.wakeups
+                        .borrow(cs)
+                        .borrow_mut()
+                        .push(AtomicBool::new(false));
+                });
+                index
+            });
+
+            let slots = self.slots.borrow();
+            let slot = &slots[index];
+            slot.future.set(Some(future));
+            slot.state.set(TaskState::Runnable);
+            slot.occupied.set(true);
+        }
+    }
+
+    // Marks woken tasks runnable, wakeups of free slots are dropped.
+    fn apply_wakeups(&self) {
+        let wake_all = take_flag(&self.wake_all);
+        let slots = self.slots.borrow();
+
+        critical_section::with(|cs| {
+            for (slot, wakeup) in slots.iter().zip(self.wakeups.borrow(cs).borrow().iter()) {
+                // Load and store, thumbv6m has no atomic swap; wakers are excluded anyway.
+                let woken = wakeup.load(Ordering::Acquire);
+                wakeup.store(false, Ordering::Relaxed);
+                if (woken || wake_all) && slot.occupied.get() {
+                    slot.state.set(TaskState::Runnable);
+                }
+            }
+        });
+    }
+
+    fn run_task(&self, index: usize, slot: &Slot<'a>) -> RunResult {
+        if !slot.occupied.get() {
+            return RunResult::NoMoreTasks;
+        }
+
+        let now = self.env.ticks();
+        if !slot.state.get().is_runnable(now) {
+            return RunResult::from_task_state(Some(slot.state.get()));
+        }
+
+        let Some(mut future) = slot.future.take() else {
+            return RunResult::NoMoreTasks;
+        };
+
+        // Let sleep and yield futures update this field.
+        slot.state.set(TaskState::Waiting(None));
+
+        match self
+            .registration
+            .poll(index, |context| future.as_mut().poll(context))
+        {
+            Poll::Ready(()) => {
+                drop(future);
+                self.release(index, slot);
+                RunResult::NoMoreTasks
+            }
+            Poll::Pending => {
+                slot.future.set(Some(future));
+                RunResult::from_task_state(Some(slot.state.get()))
+            }
+        }
+    }
+
+    fn release(&self, index: usize, slot: &Slot<'a>) {
+        if slot.occupied.replace(false) {
+            drop(slot.future.take());
+            self.free.borrow_mut().push(index);
+        }
+    }
+
+    fn signal_wakeup(&self) {
+        self.wakeup_event.store(true, Ordering::Release);
+
+        if self.env.core_id() != self.core {
+            self.env.signal_core(self.core);
+        }
+    }
+
+    // Runs `f` for an occupied slot, wakers of finished tasks are ignored.
+    fn with_slot(&self, index: usize, f: impl FnOnce(&Slot<'a>)) {
+        if let Some(slot) = self.slots.borrow().get(index)
+            && slot.occupied.get()
+        {
+            f(slot);
+        }
+    }
+}
+
+impl Executor for Inner<'_> {
+    fn current_time(&self) -> Instant {
+        self.env.ticks()
+    }
+
+    fn wakeup_task_at(&self, task_index: usize, time: Instant) -> Poll<()> {
+        if self.env.ticks() >= time {
+            return Poll::Ready(());
+        }
+
+        self.with_slot(task_index, |slot| {
+            slot.state.update(|state| {
+                // Check if the task is already scheduled to wakeup at earlier time.
+                if state.is_runnable(time) {
+                    state
+                } else {
+                    TaskState::Waiting(Some(time))
+                }
+            });
+        });
+
+        Poll::Pending
+    }
+
+    fn set_task_runnable(&self, task_index: usize) {
+        // Slot state is updated by the executor, wakers may run while it borrows slots.
+        critical_section::with(|cs| {
+            if let Some(wakeup) = self.wakeups.borrow(cs).borrow().get(task_index) {
+                wakeup.store(true, Ordering::Release);
+            }
+        });
+        self.signal_wakeup();
+    }
+
+    fn wake_all_tasks(&self) {
+        self.wake_all.store(true, Ordering::Release);
+        self.signal_wakeup();
+    }
+
+    fn shutdown(&self, grace: Duration) {
+        if self.shutdown_deadline.get().is_none() {
+            self.shutdown_deadline.set(Some(self.env.ticks() + grace));
+        }
+        self.wakeup_event.store(true, Ordering::Release);
+    }
+
+    fn task_metadata(&self, task_index: usize) -> TaskMetadata {
+        TaskMetadata {
+            index: task_index,
+            name: None,
+            tag: None,
+            priority: 0,
+        }
+    }
+
+    fn consume_budget(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::mailbox::Mailbox;
+    use crate::sim::SimEnvironment;
+    use crate::test_utils::CoreEnvironment;
+
+    #[test]
+    fn many_sleeping_tasks() {
+        let env = SimEnvironment::new();
+        let finished = Cell::new(0);
+        let executor = HeapExecutor::new(&env);
+
+        for ticks in 0..100 {
+            let finished = &finished;
+            executor.spawn(async move {
+                crate::sleep(Duration::new(ticks)).await;
+                crate::yield_once().await;
+                finished.update(|count| count + 1);
+            });
+        }
+        assert_eq!(executor.task_count(), 100);
+
+        executor.run();
+
+        assert_eq!(finished.get(), 100);
+        assert_eq!(executor.task_count(), 0);
+        assert_eq!(env.ticks(), Instant::new(99));
+    }
+
+    #[test]
+    fn spawn_from_tasks() {
+        let env = SimEnvironment::new();
+        let results = Mailbox::<u32>::new();
+        let sum = Cell::new(0);
+        let executor = HeapExecutor::new(&env);
+        let spawner = executor.spawner();
+
+        executor.spawn({
+            let (results, sum) = (&results, &sum);
+            async move {
+                for value in 1..=10 {
+                    spawner.spawn(async move {
+                        crate::sleep(Duration::new(10)).await;
+                        results.post(value);
+                    });
+                    // Take the result before the next worker posts.
+                    let result = results.read().await.unwrap();
+                    sum.update(|sum| sum + result);
+                }
+            }
+        });
+
+        executor.run();
+
+        assert_eq!(sum.get(), 55);
+        assert_eq!(env.ticks(), Instant::new(100));
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let env = SimEnvironment::new();
+        let executor = HeapExecutor::new(&env);
+
+        for _ in 0..3 {
+            executor.spawn(crate::yield_once());
+            executor.spawn(crate::yield_once());
+            executor.run();
+        }
+
+        assert_eq!(executor.inner.slots.borrow().len(), 2);
+    }
+
+    #[test]
+    fn wake_after_slots_grow() {
+        let env = SimEnvironment::new();
+        let mbox = Mailbox::<u32>::new();
+        let received = Cell::new(None);
+        let executor = HeapExecutor::new(&env);
+        let spawner = executor.spawner();
+
+        executor.spawn({
+            let (mbox, received) = (&mbox, &received);
+            async move {
+                received.set(Some(mbox.read().await.unwrap()));
+            }
+        });
+        executor.spawn({
+            let mbox = &mbox;
+            async move {
+                // Reader waits with its waker registered while slots reallocate.
+                for _ in 0..100 {
+                    spawner.spawn(crate::yield_once());
+                }
+                crate::yield_once().await;
+                mbox.post(42);
+            }
+        });
+
+        executor.run();
+
+        assert_eq!(received.get(), Some(42));
+        assert!(executor.inner.slots.borrow().len() > 100);
+    }
+
+    #[test]
+    fn wake_from_other_core() {
+        let env = CoreEnvironment::default();
+        let ping = crate::sync::mailbox::Mailbox::<u32>::new();
+        let pong = crate::sync::mailbox::Mailbox::<u32>::new();
+
+        std::thread::scope(|scope| {
+            for core in 0..2 {
+                let (env, ping, pong) = (&env, &ping, &pong);
+                scope.spawn(move || {
+                    env.enter(core);
+
+                    let executor = HeapExecutor::new(env);
+                    let spawner = executor.spawner();
+                    executor.spawn(async move {
+                        for round in 0..100 {
+                            if core == 0 {
+                                ping.post(round);
+                                // Slots grow while the other core may wake this task.
+                                spawner.spawn(crate::yield_once());
+                                assert_eq!(pong.read().await, Ok(round));
+                            } else {
+                                pong.post(ping.read().await.unwrap());
+                            }
+                        }
+                    });
+                    executor.run();
+                });
+            }
+        });
+
+        // Executors sleep until signalled, so they only finish if the hook is called.
+        assert!(env.signals() > 0);
+    }
+}
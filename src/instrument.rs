@@ -0,0 +1,77 @@
+use crate::executor::TaskMetadata;
+use crate::time::Instant;
+
+/// What made a task runnable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeSource {
+    /// Task deadline was reached.
+    Timer,
+    /// Task was woken through its waker.
+    Waker,
+}
+
+/// Receives scheduler events from the executor. All methods default to no-op.
+///
+/// Executor is generic over the instrument, so the default `()` compiles away.
+/// Events are reported with the tick they happened at.
+///
+/// Events are only reported from the executor's run loop, never from wakers, so
+/// instruments don't have to be interrupt-safe. Waker wakeups are reported with
+/// the tick the executor picked them up at.
+pub trait Instrument: core::fmt::Debug {
+    /// Whether executor should report events. Lets `()` skip reading the clock
+    /// just to timestamp events nobody listens to.
+    const ENABLED: bool = true;
+
+    /// Task is about to be polled.
+    fn poll_start(&self, _task: &TaskMetadata, _tick: Instant) {}
+    /// Task returned from `poll`.
+    fn poll_end(&self, _task: &TaskMetadata, _tick: Instant) {}
+    /// Task became runnable.
+    fn task_woken(&self, _task: &TaskMetadata, _source: WakeSource, _tick: Instant) {}
+    /// Task is waiting for a wakeup, possibly with a timer `deadline`.
+    fn task_sleep(&self, _task: &TaskMetadata, _deadline: Option<Instant>, _tick: Instant) {}
+    /// Task finished.
+    fn task_finished(&self, _task: &TaskMetadata, _tick: Instant) {}
+    /// Executor is about to sleep until wakeup event or `deadline`.
+    fn idle_enter(&self, _deadline: Option<Instant>, _tick: Instant) {}
+    /// Executor woke up.
+    fn idle_exit(&self, _tick: Instant) {}
+}
+
+impl Instrument for () {
+    const ENABLED: bool = false;
+}
+
+impl<T: Instrument + ?Sized> Instrument for &T {
+    const ENABLED: bool = T::ENABLED;
+
+    fn poll_start(&self, task: &TaskMetadata, tick: Instant) {
+        (**self).poll_start(task, tick)
+    }
+
+    fn poll_end(&self, task: &TaskMetadata, tick: Instant) {
+        (**self).poll_end(task, tick)
+    }
+
+    fn task_woken(&self, task: &TaskMetadata, source: WakeSource, tick: Instant) {
+        (**self).task_woken(task, source, tick)
+    }
+
+    fn task_sleep(&self, task: &TaskMetadata, deadline: Option<Instant>, tick: Instant) {
+        (**self).task_sleep(task, deadline, tick)
+    }
+
+    fn task_finished(&self, task: &TaskMetadata, tick: Instant) {
+        (**self).task_finished(task, tick)
+    }
+
+    fn idle_enter(&self, deadline: Option<Instant>, tick: Instant) {
+        (**self).idle_enter(deadline, tick)
+    }
+
+    fn idle_exit(&self, tick: Instant) {
+        (**self).idle_exit(tick)
+    }
+}
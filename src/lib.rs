@@ -1,12 +1,33 @@
 #![cfg_attr(not(test), no_std)]
 #![deny(clippy::unwrap_used)]
+#![cfg_attr(test, allow(clippy::unwrap_used))]
 #![deny(clippy::unwrap_in_result)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod cancellation;
+pub mod combinators;
+#[cfg(feature = "embedded-hal-async")]
+pub mod delay;
 pub mod executor;
+#[cfg(feature = "fugit")]
+pub mod fugit;
+#[cfg(feature = "alloc")]
+pub mod heap;
+pub mod instrument;
 pub mod mailbox;
+pub mod pipe;
+pub mod replay;
+pub mod sim;
 mod sleep;
 pub mod sync;
+pub mod task_local;
+pub mod task_pool;
 pub mod time;
+pub mod trace;
 mod waker;
 mod yield_once;
 
@@ -30,5 +51,16 @@ pub async fn now() -> time::Instant {
     time::CurrentTime::new().await
 }
 
+/// Returns metadata of the current task.
+pub async fn current_task() -> executor::TaskMetadata {
+    executor::CurrentTask::new().await
+}
+
+/// Requests executor shutdown: cancels its root token and drops tasks
+/// that are still running after `grace` period.
+pub async fn shutdown(grace: time::Duration) {
+    executor::Shutdown::new(grace).await
+}
+
 #[cfg(test)]
 mod test_utils;
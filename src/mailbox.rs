@@ -1,19 +1,26 @@
 #![deny(unsafe_code)]
 
 use core::cell::Cell;
+use core::convert::Infallible;
 use core::fmt::Debug;
 use core::future::Future;
+use core::pin::Pin;
 use core::task::{Context, Poll, Waker};
 
+use futures::{Sink, Stream};
 use thiserror::Error;
 
 /// Thread-unsafe mailbox, storing a single value and allowing to wait for it.
 pub struct Mailbox<T> {
     value: Cell<Option<T>>,
     waker: Cell<Option<Waker>>,
+    // `Sender` waiting for the value to be taken.
+    sender: Cell<Option<Waker>>,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Error)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Someone already waiting for this mailbox.
     /// It doesn't support multiple waiters.
@@ -41,6 +48,7 @@ impl<T> Mailbox<T> {
         Self {
             value: Cell::new(None),
             waker: Cell::new(None),
+            sender: Cell::new(None),
         }
     }
 
@@ -82,7 +90,73 @@ impl<T> Mailbox<T> {
             return Err(Error::AlreadyWaiting);
         }
 
-        Ok(self.value.take())
+        Ok(self.take())
+    }
+
+    /// Returns `Stream` of posted values.
+    /// Like `read()`, it must be the only reader waiting for this mailbox.
+    pub fn receiver(&self) -> Result<Receiver<'_, T>, Error> {
+        let waker = self.waker.take();
+        if let Some(waker) = waker {
+            // Mailbox busy.
+            // Restore waker and return error.
+            self.waker.set(Some(waker));
+            return Err(Error::AlreadyWaiting);
+        }
+
+        Ok(Receiver {
+            mailbox: self,
+            registered: false,
+        })
+    }
+
+    /// Returns `Sink` posting values, which waits for the previous one to be taken.
+    pub fn sender(&self) -> Sender<'_, T> {
+        Sender {
+            mailbox: self,
+            registered: false,
+        }
+    }
+
+    fn take(&self) -> Option<T> {
+        let value = self.value.take();
+        if value.is_some()
+            && let Some(sender) = self.sender.take()
+        {
+            sender.wake();
+        }
+        value
+    }
+
+    // Takes the value if present, otherwise registers `waker` to be woken on `post()`.
+    // Waker is registered whenever `Pending` is returned, so the caller can track it.
+    fn poll_take(&self, cx: &mut Context<'_>) -> Poll<T> {
+        if crate::executor::consume_budget(cx).is_pending() {
+            // Keep the mailbox claimed while yielding.
+            self.waker.set(Some(cx.waker().clone()));
+            return Poll::Pending;
+        }
+
+        match self.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                self.waker.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_empty(&self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        let value = self.value.take();
+        let full = value.is_some();
+        self.value.set(value);
+
+        if full {
+            self.sender.set(Some(cx.waker().clone()));
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
     }
 }
 
@@ -93,14 +167,8 @@ struct MailboxFuture<'a, T> {
 impl<T> Future for MailboxFuture<'_, T> {
     type Output = T;
 
-    fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.mailbox.value.take() {
-            Some(value) => Poll::Ready(value),
-            None => {
-                self.mailbox.waker.set(Some(cx.waker().clone()));
-                Poll::Pending
-            }
-        }
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.mailbox.poll_take(cx)
     }
 }
 
@@ -110,12 +178,95 @@ impl<T> Drop for MailboxFuture<'_, T> {
     }
 }
 
+/// `Stream` view of `Mailbox`, never ends.
+pub struct Receiver<'a, T> {
+    mailbox: &'a Mailbox<T>,
+    // Waker of the last pending poll is stored in the mailbox.
+    registered: bool,
+}
+
+impl<T> Debug for Receiver<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Stream for Receiver<'_, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        let poll = this.mailbox.poll_take(cx);
+        this.registered = poll.is_pending();
+        poll.map(Some)
+    }
+}
+
+impl<T> Drop for Receiver<'_, T> {
+    fn drop(&mut self) {
+        // Other readers may wait once this one is done, keep their waker.
+        if self.registered {
+            self.mailbox.waker.set(None);
+        }
+    }
+}
+
+/// `Sink` view of `Mailbox`. Flushing waits until the last value is taken.
+pub struct Sender<'a, T> {
+    mailbox: &'a Mailbox<T>,
+    // Waker of the last pending poll is stored in the mailbox.
+    registered: bool,
+}
+
+impl<T> Sender<'_, T> {
+    fn poll_empty(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        let poll = self.mailbox.poll_empty(cx);
+        self.registered = poll.is_pending();
+        poll
+    }
+}
+
+impl<T> Debug for Sender<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Sink<T> for Sender<'_, T> {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Infallible> {
+        self.mailbox.post(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+}
+
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        if self.registered {
+            self.mailbox.sender.set(None);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::pin::pin;
 
-    use futures::join;
     use futures::task::LocalFutureObj;
+    use futures::{SinkExt, StreamExt, join};
 
     use super::*;
     use crate::executor::LocalExecutor;
@@ -228,4 +379,88 @@ mod tests {
         assert_eq!(u, Err(Error::AlreadyWaiting));
         assert_eq!(w, None);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&Error::AlreadyWaiting).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Error>(&json).unwrap(),
+            Error::AlreadyWaiting
+        );
+    }
+
+    #[test]
+    fn stream_and_sink() {
+        let mbox = Mailbox::<i32>::new();
+
+        let (sent, received) = block_on(async {
+            join!(
+                async {
+                    let mut sender = mbox.sender();
+                    for value in 1..=5 {
+                        sender.send(value).await.unwrap();
+                    }
+                    // `send` flushes, so every value was taken before the next one was posted.
+                    mbox.try_read().unwrap()
+                },
+                mbox.receiver()
+                    .unwrap()
+                    .map(|value| value * 10)
+                    .take(5)
+                    .collect::<Vec<_>>()
+            )
+        });
+
+        assert_eq!(sent, None);
+        assert_eq!(received, [10, 20, 30, 40, 50]);
+    }
+
+    #[test]
+    fn idle_stream_and_sink_keep_other_waiters() {
+        let mbox = Mailbox::<i32>::new();
+
+        let receiver = mbox.receiver().unwrap();
+        let sender = mbox.sender();
+
+        let (read, busy) = block_on(async {
+            Some(join!(mbox.read(), async {
+                // Neither was polled, so dropping them must keep the reader's waker.
+                drop(receiver);
+                drop(sender);
+                let busy = mbox.receiver().err();
+                mbox.post(42);
+                busy
+            }))
+        })
+        .expect("coroutine returned None");
+
+        assert_eq!(read, Ok(42));
+        assert_eq!(busy, Some(Error::AlreadyWaiting));
+    }
+
+    #[test]
+    fn yielding_receiver_keeps_mailbox_claimed() {
+        let env = TestEnvironment::new();
+        let mbox = Mailbox::<i32>::new();
+        let checked = Cell::new(false);
+        let mut f = pin!(async {
+            let mut receiver = mbox.receiver().unwrap();
+            mbox.post(1);
+            assert_eq!(receiver.next().await, Some(1));
+            // Coop budget is used up, so the receiver yields without a value.
+            assert!(futures::poll!(receiver.next()).is_pending());
+            assert_eq!(mbox.try_read(), Err(Error::AlreadyWaiting));
+            drop(receiver);
+            assert_eq!(mbox.try_read(), Ok(None));
+            checked.set(true);
+        });
+
+        LocalExecutor::new(&env)
+            .with_coop_budget(1)
+            .run([LocalFutureObj::new(&mut f)]);
+
+        assert!(checked.get());
+    }
 }
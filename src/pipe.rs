@@ -0,0 +1,376 @@
+#![deny(unsafe_code)]
+
+use core::cell::{Cell, RefCell};
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Someone already waiting to read or write this pipe.
+    /// It supports one reader and one writer at a time.
+    #[error("pipe already awaited")]
+    AlreadyWaiting,
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        embedded_io_async::ErrorKind::Other
+    }
+}
+
+/// Ring buffer shared by `Pipe` and `sync::pipe::Pipe`.
+#[derive(Debug)]
+pub(crate) struct Ring<const N: usize> {
+    buffer: [u8; N],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Appends as much of `data` as fits, returns number of bytes written.
+    pub(crate) fn push(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(N - self.len);
+        for (offset, byte) in data[..count].iter().enumerate() {
+            self.buffer[(self.start + self.len + offset) % N] = *byte;
+        }
+        self.len += count;
+        count
+    }
+
+    /// Fills as much of `buf` as possible, returns number of bytes read.
+    pub(crate) fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.len);
+        for (offset, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.buffer[(self.start + offset) % N];
+        }
+        if count > 0 {
+            self.start = (self.start + count) % N;
+            self.len -= count;
+        }
+        count
+    }
+}
+
+/// Thread-unsafe byte pipe with capacity of `N` bytes.
+pub struct Pipe<const N: usize> {
+    ring: RefCell<Ring<N>>,
+    reader: Cell<Option<Waker>>,
+    writer: Cell<Option<Waker>>,
+}
+
+impl<const N: usize> Debug for Pipe<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pipe").field("len", &self.len()).finish()
+    }
+}
+
+impl<const N: usize> Default for Pipe<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Pipe<N> {
+    /// Creates empty pipe.
+    pub const fn new() -> Self {
+        Self {
+            ring: RefCell::new(Ring::new()),
+            reader: Cell::new(None),
+            writer: Cell::new(None),
+        }
+    }
+
+    /// Returns number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.ring.borrow().len()
+    }
+
+    /// Checks if there are no buffered bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits pipe into reading and writing halves.
+    pub fn split(&self) -> (Reader<'_, N>, Writer<'_, N>) {
+        (Reader { pipe: self }, Writer { pipe: self })
+    }
+
+    /// Waits for data and reads up to `buf.len()` bytes, returning number of bytes read.
+    /// Returns 0 only if `buf` is empty.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        check_idle(&self.reader)?;
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        Ok(ReadFuture { pipe: self, buf }.await)
+    }
+
+    /// Waits for free space and writes up to `data.len()` bytes, returning number of
+    /// bytes written. Returns 0 only if `data` is empty.
+    pub async fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        check_idle(&self.writer)?;
+
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        Ok(WriteFuture { pipe: self, data }.await)
+    }
+
+    /// Reads buffered bytes without waiting, returns number of bytes read.
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        let count = self.ring.borrow_mut().pop(buf);
+        if count > 0 {
+            wake(&self.writer);
+        }
+        count
+    }
+
+    /// Writes bytes that fit without waiting, returns number of bytes written.
+    pub fn try_write(&self, data: &[u8]) -> usize {
+        let count = self.ring.borrow_mut().push(data);
+        if count > 0 {
+            wake(&self.reader);
+        }
+        count
+    }
+}
+
+fn check_idle(waker: &Cell<Option<Waker>>) -> Result<(), Error> {
+    match waker.take() {
+        Some(waker_value) => {
+            // Pipe busy.
+            // Restore waker and return error.
+            waker.set(Some(waker_value));
+            Err(Error::AlreadyWaiting)
+        }
+        None => Ok(()),
+    }
+}
+
+fn wake(waker: &Cell<Option<Waker>>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+struct ReadFuture<'a, 'b, const N: usize> {
+    pipe: &'a Pipe<N>,
+    buf: &'b mut [u8],
+}
+
+impl<const N: usize> Future for ReadFuture<'_, '_, N> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        core::task::ready!(crate::executor::consume_budget(cx));
+
+        let this = self.get_mut();
+        match this.pipe.try_read(this.buf) {
+            0 => {
+                this.pipe.reader.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            count => Poll::Ready(count),
+        }
+    }
+}
+
+impl<const N: usize> Drop for ReadFuture<'_, '_, N> {
+    fn drop(&mut self) {
+        self.pipe.reader.set(None);
+    }
+}
+
+struct WriteFuture<'a, 'b, const N: usize> {
+    pipe: &'a Pipe<N>,
+    data: &'b [u8],
+}
+
+impl<const N: usize> Future for WriteFuture<'_, '_, N> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        core::task::ready!(crate::executor::consume_budget(cx));
+
+        match self.pipe.try_write(self.data) {
+            0 => {
+                self.pipe.writer.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            count => Poll::Ready(count),
+        }
+    }
+}
+
+impl<const N: usize> Drop for WriteFuture<'_, '_, N> {
+    fn drop(&mut self) {
+        self.pipe.writer.set(None);
+    }
+}
+
+/// Reading half of `Pipe`.
+#[derive(Debug, Clone, Copy)]
+pub struct Reader<'a, const N: usize> {
+    pipe: &'a Pipe<N>,
+}
+
+impl<const N: usize> Reader<'_, N> {
+    /// See `Pipe::read`.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.pipe.read(buf).await
+    }
+}
+
+/// Writing half of `Pipe`.
+#[derive(Debug, Clone, Copy)]
+pub struct Writer<'a, const N: usize> {
+    pipe: &'a Pipe<N>,
+}
+
+impl<const N: usize> Writer<'_, N> {
+    /// See `Pipe::write`.
+    pub async fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pipe.write(data).await
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::ErrorType for Reader<'_, N> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::Read for Reader<'_, N> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.pipe.read(buf).await
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::ErrorType for Writer<'_, N> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::Write for Writer<'_, N> {
+    async fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pipe.write(data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::join;
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::LocalExecutor;
+    use crate::test_utils::{TestEnvironment, block_on};
+
+    #[test]
+    fn ring_wraps_around() {
+        let mut ring = Ring::<4>::new();
+        let mut buf = [0; 4];
+
+        assert_eq!(ring.push(&[1, 2, 3]), 3);
+        assert_eq!(ring.pop(&mut buf[..2]), 2);
+        assert_eq!(ring.push(&[4, 5, 6, 7]), 3);
+        assert_eq!(ring.pop(&mut buf), 4);
+        assert_eq!(buf, [3, 4, 5, 6]);
+        assert_eq!(ring.len(), 0);
+    }
+
+    #[test]
+    fn stream_between_tasks() {
+        let pipe = Pipe::<8>::new();
+        let (mut reader, mut writer) = pipe.split();
+        let mut received = Vec::new();
+
+        {
+            let mut producer = pin!(async {
+                let data: Vec<u8> = (0..100).collect();
+                let mut sent = 0;
+                while sent < data.len() {
+                    sent += writer.write(&data[sent..]).await.unwrap();
+                }
+            });
+            let mut consumer = pin!(async {
+                let mut buf = [0; 5];
+                while received.len() < 100 {
+                    let count = reader.read(&mut buf).await.unwrap();
+                    received.extend_from_slice(&buf[..count]);
+                }
+            });
+
+            let env = TestEnvironment::new();
+            LocalExecutor::new(&env).run([
+                LocalFutureObj::new(&mut producer),
+                LocalFutureObj::new(&mut consumer),
+            ]);
+        }
+
+        assert_eq!(received, (0..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn double_read_returns_error() {
+        let pipe = Pipe::<4>::new();
+
+        let (t, u, w) = block_on(async {
+            Some(join!(
+                async { pipe.read(&mut [0; 2]).await },
+                async { pipe.read(&mut [0; 2]).await },
+                async { pipe.try_write(&[1, 2, 3]) }
+            ))
+        })
+        .expect("coroutine returned None");
+
+        assert_eq!(t, Ok(2));
+        assert_eq!(u, Err(Error::AlreadyWaiting));
+        assert_eq!(w, 3);
+        assert_eq!(pipe.len(), 1);
+    }
+
+    #[cfg(feature = "embedded-io-async")]
+    #[test]
+    fn embedded_io() {
+        use embedded_io_async::{Read, Write};
+
+        async fn echo(reader: &mut impl Read, writer: &mut impl Write) -> [u8; 6] {
+            writer.write_all(b"hello!").await.unwrap();
+            let mut buf = [0; 6];
+            reader.read_exact(&mut buf).await.unwrap();
+            buf
+        }
+
+        let pipe = Pipe::<16>::new();
+        let (mut reader, mut writer) = pipe.split();
+
+        let buf = block_on(echo(&mut reader, &mut writer));
+
+        assert_eq!(&buf, b"hello!");
+    }
+}
@@ -0,0 +1,367 @@
+#![deny(unsafe_code)]
+
+use core::cell::Cell;
+use core::fmt::Debug;
+
+use portable_atomic::AtomicBool;
+
+use crate::executor::{Environment, LocalExecutor, Task, TaskMetadata};
+use crate::instrument::{Instrument, WakeSource};
+use crate::time::Instant;
+use crate::trace::{RECORD_SIZE, decode_record, decode_records, encode_record};
+
+/// Scheduling decision: which task was polled, when, and what woke it.
+/// Initial poll of a task is recorded as woken by waker.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Decision {
+    pub task: u16,
+    pub tick: Instant,
+    pub wake: WakeSource,
+}
+
+impl Decision {
+    /// Size of the binary encoding.
+    pub const SIZE: usize = RECORD_SIZE;
+
+    const EMPTY: Self = Self {
+        task: 0,
+        tick: Instant::new(0),
+        wake: WakeSource::Waker,
+    };
+
+    /// Encodes decision as little-endian tick, task index and wake source.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let wake = match self.wake {
+            WakeSource::Timer => 0,
+            WakeSource::Waker => 1,
+        };
+        encode_record(self.tick, self.task, wake)
+    }
+
+    /// Decodes decision written by `to_bytes`. Returns None for unknown wake source.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let (tick, task, wake) = decode_record(bytes);
+
+        Some(Self {
+            tick,
+            task,
+            wake: match wake {
+                0 => WakeSource::Timer,
+                1 => WakeSource::Waker,
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// Decodes a dump of `Recorder`, stopping at the first malformed decision.
+pub fn decode(bytes: &[u8]) -> impl Iterator<Item = Decision> + '_ {
+    decode_records(bytes, Decision::from_bytes)
+}
+
+// Timer wakeup is reported right before the poll it causes.
+#[derive(Debug, Default)]
+struct WakeTracker {
+    timer_woken: Cell<Option<usize>>,
+}
+
+impl WakeTracker {
+    fn timer_wake(&self, task: &TaskMetadata, source: WakeSource) {
+        if source == WakeSource::Timer {
+            self.timer_woken.set(Some(task.index));
+        }
+    }
+
+    fn decision(&self, task: &TaskMetadata, tick: Instant) -> Decision {
+        let wake = if self.timer_woken.take() == Some(task.index) {
+            WakeSource::Timer
+        } else {
+            WakeSource::Waker
+        };
+
+        Decision {
+            task: u16::try_from(task.index).unwrap_or(u16::MAX),
+            tick,
+            wake,
+        }
+    }
+}
+
+/// Records the first `CAP` scheduling decisions of a run.
+/// Pass it to `LocalExecutor::with_instrument` by reference.
+pub struct Recorder<const CAP: usize> {
+    decisions: [Cell<Decision>; CAP],
+    len: Cell<usize>,
+    truncated: Cell<bool>,
+    wakes: WakeTracker,
+}
+
+impl<const CAP: usize> Debug for Recorder<CAP> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("len", &self.len.get())
+            .field("truncated", &self.truncated.get())
+            .finish()
+    }
+}
+
+impl<const CAP: usize> Default for Recorder<CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CAP: usize> Recorder<CAP> {
+    /// Creates empty recorder.
+    pub const fn new() -> Self {
+        Self {
+            decisions: [const { Cell::new(Decision::EMPTY) }; CAP],
+            len: Cell::new(0),
+            truncated: Cell::new(false),
+            wakes: WakeTracker {
+                timer_woken: Cell::new(None),
+            },
+        }
+    }
+
+    /// Iterates over recorded decisions.
+    pub fn iter(&self) -> impl Iterator<Item = Decision> + '_ {
+        self.decisions[..self.len.get()].iter().map(Cell::get)
+    }
+
+    /// Checks if some decisions didn't fit.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.get()
+    }
+
+    /// Writes binary encoding of recorded decisions to `out`.
+    pub fn dump(&self, mut out: impl FnMut(&[u8])) {
+        self.iter().for_each(|decision| out(&decision.to_bytes()));
+    }
+}
+
+impl<const CAP: usize> Instrument for Recorder<CAP> {
+    fn task_woken(&self, task: &TaskMetadata, source: WakeSource, _tick: Instant) {
+        self.wakes.timer_wake(task, source);
+    }
+
+    fn poll_start(&self, task: &TaskMetadata, tick: Instant) {
+        let decision = self.wakes.decision(task, tick);
+        let len = self.len.get();
+
+        match self.decisions.get(len) {
+            Some(slot) => {
+                slot.set(decision);
+                self.len.set(len + 1);
+            }
+            None => self.truncated.set(true),
+        }
+    }
+}
+
+/// Replays a recording, see `LocalExecutor::with_replay`.
+///
+/// Serves as environment returning the recorded tick of the next decision, so timers
+/// fire as they did, and as instrument checking what woke each polled task.
+/// Time doesn't advance during a poll, each poll lasts zero ticks.
+#[derive(Debug)]
+pub struct Replayer<'a> {
+    expected: &'a [Decision],
+    // Index of the next decision, advanced when its poll ends.
+    position: Cell<usize>,
+    // Position at which the executor last asked for a task to poll.
+    requested: Cell<Option<usize>>,
+    wakes: WakeTracker,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(expected: &'a [Decision]) -> Self {
+        Self {
+            expected,
+            position: Cell::new(0),
+            requested: Cell::new(None),
+            wakes: WakeTracker::default(),
+        }
+    }
+
+    /// Checks if all recorded decisions were replayed.
+    pub fn is_complete(&self) -> bool {
+        self.position.get() == self.expected.len()
+    }
+
+    /// Returns index of the task to poll next.
+    pub(crate) fn next_task(&self) -> usize {
+        let position = self.position.get();
+        let Some(decision) = self.expected.get(position) else {
+            panic!("replay diverged: tasks are still running after the end of recording");
+        };
+
+        // Every iteration polls the recorded task, unless it isn't runnable.
+        if self.requested.replace(Some(position)) == Some(position) {
+            panic!(
+                "replay diverged at decision {position}: task {} is not runnable",
+                decision.task
+            );
+        }
+
+        usize::from(decision.task)
+    }
+}
+
+impl Environment for Replayer<'_> {
+    fn wait_for_event_with_deadline(&self, _event: &AtomicBool, _tick: Option<Instant>) {
+        // Time jumps to the next decision, the executor detects if its task isn't runnable.
+    }
+
+    fn ticks(&self) -> Instant {
+        self.expected
+            .get(self.position.get())
+            .or(self.expected.last())
+            .map_or(Instant::new(0), |decision| decision.tick)
+    }
+}
+
+impl Instrument for Replayer<'_> {
+    fn task_woken(&self, task: &TaskMetadata, source: WakeSource, _tick: Instant) {
+        self.wakes.timer_wake(task, source);
+    }
+
+    fn poll_start(&self, task: &TaskMetadata, tick: Instant) {
+        let actual = self.wakes.decision(task, tick);
+        let position = self.position.get();
+
+        match self.expected.get(position) {
+            Some(expected) => {
+                assert_eq!(*expected, actual, "replay diverged at decision {position}")
+            }
+            None => {
+                panic!("replay diverged: unexpected decision {actual:?} after the end of recording")
+            }
+        }
+    }
+
+    fn poll_end(&self, _task: &TaskMetadata, _tick: Instant) {
+        self.position.update(|position| position + 1);
+    }
+}
+
+/// Runs tasks polling them in the order and at the ticks of `recording`, so a run
+/// captured by `Recorder` on hardware can be debugged on host.
+///
+/// # Panics
+///
+/// Panics if the run diverges from the recording.
+pub fn replay<'f, const N: usize>(recording: &[Decision], tasks: [impl Into<Task<'f>>; N]) {
+    let replayer = Replayer::new(recording);
+
+    LocalExecutor::new(&replayer)
+        .with_replay(&replayer)
+        .run(tasks);
+
+    assert!(
+        replayer.is_complete(),
+        "replay diverged: tasks finished before the end of recording"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::SchedulingPolicy;
+    use crate::mailbox::Mailbox;
+    use crate::test_utils::TestEnvironment;
+    use crate::time::Duration;
+
+    // Runs client and server tasks with `run`, returns what the client received and when.
+    fn run_protocol(run: impl FnOnce([LocalFutureObj<'_, ()>; 2])) -> Vec<(u32, Instant)> {
+        let request = Mailbox::<u32>::new();
+        let response = Mailbox::<u32>::new();
+        let log = RefCell::new(Vec::new());
+
+        let mut client = pin!(async {
+            for value in 0..3 {
+                request.post(value);
+                let response = response.read().await.unwrap();
+                let now = crate::now().await;
+                log.borrow_mut().push((response, now));
+                crate::sleep(Duration::new(100)).await;
+            }
+        });
+        let mut server = pin!(async {
+            for _ in 0..3 {
+                let value = request.read().await.unwrap();
+                crate::yield_once().await;
+                response.post(value + 1);
+            }
+        });
+
+        run([
+            LocalFutureObj::new(&mut client),
+            LocalFutureObj::new(&mut server),
+        ]);
+        log.take()
+    }
+
+    fn record(seed: u64) -> (Vec<Decision>, Vec<(u32, Instant)>) {
+        let env = TestEnvironment::new();
+        let recorder = Recorder::<64>::new();
+        let log = run_protocol(|tasks| {
+            LocalExecutor::new(&env)
+                .with_policy(SchedulingPolicy::Random { seed })
+                .with_instrument(&recorder)
+                .run(tasks)
+        });
+        assert!(!recorder.is_truncated());
+
+        let mut bytes = Vec::new();
+        recorder.dump(|chunk| bytes.extend_from_slice(chunk));
+        (decode(&bytes).collect(), log)
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let (recording, log) = record(7);
+        assert!(
+            recording
+                .iter()
+                .any(|decision| decision.wake == WakeSource::Timer)
+        );
+
+        // Environment and policy differ, replay reproduces order and time anyway.
+        let replayed = run_protocol(|tasks| replay(&recording, tasks));
+        assert_eq!(
+            replayed.iter().map(|(value, _)| *value).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        for ((_, replayed), (_, recorded)) in replayed.iter().zip(&log) {
+            // Time doesn't pass within a replayed poll.
+            assert!(recording.iter().any(|decision| decision.tick == *replayed));
+            assert!(replayed <= recorded);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "replay diverged")]
+    fn divergence_detected() {
+        let (mut recording, _) = record(7);
+        let last = recording.len() - 1;
+        recording[last].task ^= 1;
+
+        run_protocol(|tasks| replay(&recording, tasks));
+    }
+
+    #[test]
+    #[should_panic(expected = "replay diverged")]
+    fn truncated_recording_detected() {
+        let (mut recording, _) = record(7);
+        recording.pop();
+
+        run_protocol(|tasks| replay(&recording, tasks));
+    }
+}
@@ -0,0 +1,196 @@
+#![deny(unsafe_code)]
+
+use core::cell::Cell;
+use core::sync::atomic::Ordering;
+
+use portable_atomic::AtomicBool;
+
+use crate::executor::{Environment, LocalExecutor, SchedulingPolicy, Task};
+use crate::time::{Duration, Instant};
+
+/// Environment with virtual time, which jumps straight to the next deadline
+/// instead of sleeping.
+#[derive(Debug)]
+pub struct SimEnvironment {
+    now: Cell<Instant>,
+}
+
+impl SimEnvironment {
+    /// Creates environment starting at tick 0.
+    pub fn new() -> Self {
+        Self {
+            now: Cell::new(Instant::new(0)),
+        }
+    }
+
+    /// Moves virtual time forward.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for SimEnvironment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment for SimEnvironment {
+    fn wait_for_event_with_deadline(&self, event: &AtomicBool, tick: Option<Instant>) {
+        if event.load(Ordering::Acquire) {
+            return;
+        }
+
+        match tick {
+            Some(tick) => self.now.set(self.now.get().max(tick)),
+            // Nothing outside of the simulation can wake the tasks.
+            None => panic!("simulation deadlocked at {}", self.now.get()),
+        }
+    }
+
+    fn ticks(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+/// Runs tasks to completion in virtual time, polling them in order derived from `seed`,
+/// so a failing interleaving reproduces from its seed.
+/// Returns virtual time at which the last task finished.
+///
+/// # Panics
+///
+/// Panics if all tasks wait for events that can never happen.
+pub fn simulate<'f, const N: usize>(seed: u64, tasks: [impl Into<Task<'f>>; N]) -> Instant {
+    let env = SimEnvironment::new();
+
+    LocalExecutor::new(&env)
+        .with_policy(SchedulingPolicy::Random { seed })
+        .run(tasks);
+
+    env.ticks()
+}
+
+/// SplitMix64 generator, small and good enough to shuffle tasks.
+#[derive(Debug)]
+pub(crate) struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    pub(crate) const fn new(seed: u64) -> Self {
+        Self {
+            state: Cell::new(seed),
+        }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Fisher-Yates shuffle.
+    pub(crate) fn shuffle<T>(&self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = (self.next_u64() % (index as u64 + 1)) as usize;
+            items.swap(index, other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::{mailbox, sync};
+
+    fn interleaving(seed: u64) -> Vec<u32> {
+        let log = RefCell::new(Vec::new());
+        let worker = |id| {
+            let log = &log;
+            async move {
+                for _ in 0..3 {
+                    log.borrow_mut().push(id);
+                    crate::yield_once().await;
+                }
+            }
+        };
+
+        let mut f1 = pin!(worker(1));
+        let mut f2 = pin!(worker(2));
+        let mut f3 = pin!(worker(3));
+        simulate(
+            seed,
+            [
+                LocalFutureObj::new(&mut f1),
+                LocalFutureObj::new(&mut f2),
+                LocalFutureObj::new(&mut f3),
+            ],
+        );
+
+        log.take()
+    }
+
+    #[test]
+    fn seed_reproduces_interleaving() {
+        for seed in 0..10 {
+            assert_eq!(interleaving(seed), interleaving(seed));
+        }
+
+        let distinct = (0..10)
+            .map(interleaving)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    fn virtual_time_with_mailboxes() {
+        let local = mailbox::Mailbox::<u32>::new();
+        let shared = sync::mailbox::Mailbox::<u32>::new();
+        let received = Cell::new(0);
+
+        let mut producer = pin!(async {
+            for value in 1..=3 {
+                crate::sleep(Duration::new(1000)).await;
+                local.post(value);
+                shared.post(value * 10);
+            }
+        });
+        let mut consumer = pin!(async {
+            for _ in 0..3 {
+                let value = local.read().await.unwrap() + shared.read().await.unwrap();
+                received.update(|sum| sum + value);
+            }
+        });
+
+        let end = simulate(
+            42,
+            [
+                LocalFutureObj::new(&mut producer),
+                LocalFutureObj::new(&mut consumer),
+            ],
+        );
+
+        assert_eq!(received.get(), 66);
+        assert_eq!(end, Instant::new(3000));
+    }
+
+    #[test]
+    #[should_panic(expected = "simulation deadlocked")]
+    fn deadlock_detected() {
+        let mbox = mailbox::Mailbox::<u32>::new();
+        let mut f = pin!(async {
+            mbox.read().await.unwrap();
+        });
+
+        simulate(0, [LocalFutureObj::new(&mut f)]);
+    }
+}
@@ -21,9 +21,9 @@ impl Future for Sleep {
     type Output = ();
 
     fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let info = crate::waker::from_waker(cx.waker());
-        info.executor()
-            .wakeup_task_at(info.task_index(), self.wake_at_tick)
+        crate::waker::with_current(cx.waker(), |executor, task_index| {
+            executor.wakeup_task_at(task_index, self.wake_at_tick)
+        })
     }
 }
 
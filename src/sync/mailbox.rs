@@ -1,10 +1,13 @@
 #![deny(unsafe_code)]
 
 use core::cell::Cell;
+use core::convert::Infallible;
 use core::fmt::Debug;
 use core::future::Future;
+use core::pin::Pin;
 use core::task::{Context, Poll, Waker};
-use critical_section::Mutex;
+use critical_section::{CriticalSection, Mutex};
+use futures::{Sink, Stream};
 
 pub use crate::mailbox::Error;
 
@@ -12,6 +15,8 @@ pub use crate::mailbox::Error;
 pub struct Mailbox<T> {
     value: Mutex<Cell<Option<T>>>,
     waker: Mutex<Cell<Option<Waker>>>,
+    // `Sender` waiting for the value to be taken.
+    sender: Mutex<Cell<Option<Waker>>>,
 }
 
 impl<T: Debug + Copy> Debug for Mailbox<T> {
@@ -34,6 +39,7 @@ impl<T> Mailbox<T> {
         Self {
             value: Mutex::new(Cell::new(None)),
             waker: Mutex::new(Cell::new(None)),
+            sender: Mutex::new(Cell::new(None)),
         }
     }
 
@@ -76,10 +82,10 @@ impl<T> Mailbox<T> {
     /// Reads the value from the mailbox without waiting.
     /// If there is no posted value, returns None.
     pub fn try_read(&self) -> Result<Option<T>, Error> {
-        critical_section::with(|cs| {
+        let (value, sender) = critical_section::with(|cs| {
             let waker = self.waker.borrow(cs).take();
             match waker {
-                None => Ok(self.value.borrow(cs).take()),
+                None => Ok(self.take(cs)),
                 Some(_) => {
                     // Mailbox busy.
                     // Restore waker and return error.
@@ -87,6 +93,95 @@ impl<T> Mailbox<T> {
                     Err(Error::AlreadyWaiting)
                 }
             }
+        })?;
+
+        if let Some(sender) = sender {
+            sender.wake();
+        }
+
+        Ok(value)
+    }
+
+    /// Returns `Stream` of posted values.
+    /// Like `read()`, it must be the only reader waiting for this mailbox.
+    pub fn receiver(&self) -> Result<Receiver<'_, T>, Error> {
+        critical_section::with(|cs| {
+            let waker = self.waker.borrow(cs).take();
+            match waker {
+                Some(waker) => {
+                    // Mailbox busy.
+                    // Restore waker and return error.
+                    self.waker.borrow(cs).set(Some(waker));
+                    Err(Error::AlreadyWaiting)
+                }
+                _ => Ok(()),
+            }
+        })?;
+
+        Ok(Receiver {
+            mailbox: self,
+            registered: false,
+        })
+    }
+
+    /// Returns `Sink` posting values, which waits for the previous one to be taken.
+    pub fn sender(&self) -> Sender<'_, T> {
+        Sender {
+            mailbox: self,
+            registered: false,
+        }
+    }
+
+    // Returns sender to wake outside of the critical section.
+    fn take(&self, cs: CriticalSection) -> (Option<T>, Option<Waker>) {
+        let value = self.value.borrow(cs).take();
+        let sender = match value {
+            Some(_) => self.sender.borrow(cs).take(),
+            None => None,
+        };
+        (value, sender)
+    }
+
+    // Takes the value if present, otherwise registers `waker` to be woken on `post()`.
+    // Waker is registered whenever `Pending` is returned, so the caller can track it.
+    fn poll_take(&self, cx: &mut Context<'_>) -> Poll<T> {
+        if crate::executor::consume_budget(cx).is_pending() {
+            // Keep the mailbox claimed while yielding.
+            critical_section::with(|cs| self.waker.borrow(cs).set(Some(cx.waker().clone())));
+            return Poll::Pending;
+        }
+
+        let (value, sender) = critical_section::with(|cs| {
+            let (value, sender) = self.take(cs);
+            if value.is_none() {
+                self.waker.borrow(cs).set(Some(cx.waker().clone()));
+            }
+            (value, sender)
+        });
+
+        if let Some(sender) = sender {
+            sender.wake();
+        }
+
+        match value {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+
+    fn poll_empty(&self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        critical_section::with(|cs| {
+            let value = self.value.borrow(cs);
+            let current = value.take();
+            let full = current.is_some();
+            value.set(current);
+
+            if full {
+                self.sender.borrow(cs).set(Some(cx.waker().clone()));
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(()))
+            }
         })
     }
 }
@@ -98,14 +193,8 @@ struct MailboxFuture<'a, T> {
 impl<T> Future for MailboxFuture<'_, T> {
     type Output = T;
 
-    fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        critical_section::with(|cs| match self.mailbox.value.borrow(cs).take() {
-            Some(value) => Poll::Ready(value),
-            None => {
-                self.mailbox.waker.borrow(cs).set(Some(cx.waker().clone()));
-                Poll::Pending
-            }
-        })
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.mailbox.poll_take(cx)
     }
 }
 
@@ -115,16 +204,99 @@ impl<T> Drop for MailboxFuture<'_, T> {
     }
 }
 
+/// `Stream` view of `Mailbox`, never ends.
+pub struct Receiver<'a, T> {
+    mailbox: &'a Mailbox<T>,
+    // Waker of the last pending poll is stored in the mailbox.
+    registered: bool,
+}
+
+impl<T> Debug for Receiver<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("sync::Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Stream for Receiver<'_, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        let poll = this.mailbox.poll_take(cx);
+        this.registered = poll.is_pending();
+        poll.map(Some)
+    }
+}
+
+impl<T> Drop for Receiver<'_, T> {
+    fn drop(&mut self) {
+        // Other readers may wait once this one is done, keep their waker.
+        if self.registered {
+            critical_section::with(|cs| self.mailbox.waker.borrow(cs).set(None));
+        }
+    }
+}
+
+/// `Sink` view of `Mailbox`. Flushing waits until the last value is taken.
+pub struct Sender<'a, T> {
+    mailbox: &'a Mailbox<T>,
+    // Waker of the last pending poll is stored in the mailbox.
+    registered: bool,
+}
+
+impl<T> Sender<'_, T> {
+    fn poll_empty(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        let poll = self.mailbox.poll_empty(cx);
+        self.registered = poll.is_pending();
+        poll
+    }
+}
+
+impl<T> Debug for Sender<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("sync::Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Sink<T> for Sender<'_, T> {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Infallible> {
+        self.mailbox.post(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.get_mut().poll_empty(cx)
+    }
+}
+
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        if self.registered {
+            critical_section::with(|cs| self.mailbox.sender.borrow(cs).set(None));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::pin::pin;
 
-    use futures::join;
     use futures::task::LocalFutureObj;
+    use futures::{SinkExt, StreamExt, join};
 
     use super::*;
     use crate::executor::LocalExecutor;
-    use crate::test_utils::{TestEnvironment, block_on};
+    use crate::test_utils::{CoreEnvironment, TestEnvironment, block_on};
 
     async fn post_and_read<T, U>(mbox1: &Mailbox<T>, value: T, mbox2: &Mailbox<U>) -> U {
         mbox1.post(value);
@@ -233,4 +405,82 @@ mod tests {
         assert_eq!(u, Err(Error::AlreadyWaiting));
         assert_eq!(w, None);
     }
+
+    #[test]
+    fn stream_from_other_thread() {
+        let mbox = Mailbox::<i32>::new();
+        let env = CoreEnvironment::default();
+        let mut received = Vec::new();
+
+        // Executors on two threads posing as cores, waking each other through the mailbox.
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                env.enter(1);
+                let mut sender = pin!(async {
+                    let mut sender = mbox.sender();
+                    for value in 1..=5 {
+                        sender.send(value).await.unwrap();
+                    }
+                });
+
+                LocalExecutor::new(&env).run([LocalFutureObj::new(&mut sender)]);
+            });
+
+            env.enter(0);
+            let mut receiver = pin!(async {
+                received = mbox.receiver().unwrap().take(5).collect().await;
+            });
+
+            LocalExecutor::new(&env).run([LocalFutureObj::new(&mut receiver)]);
+        });
+
+        assert_eq!(received, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn idle_stream_and_sink_keep_other_waiters() {
+        let mbox = Mailbox::<i32>::new();
+
+        let receiver = mbox.receiver().unwrap();
+        let sender = mbox.sender();
+
+        let (read, busy) = block_on(async {
+            Some(join!(mbox.read(), async {
+                // Neither was polled, so dropping them must keep the reader's waker.
+                drop(receiver);
+                drop(sender);
+                let busy = mbox.receiver().err();
+                mbox.post(42);
+                busy
+            }))
+        })
+        .expect("coroutine returned None");
+
+        assert_eq!(read, Ok(42));
+        assert_eq!(busy, Some(Error::AlreadyWaiting));
+    }
+
+    #[test]
+    fn yielding_receiver_keeps_mailbox_claimed() {
+        let env = TestEnvironment::new();
+        let mbox = Mailbox::<i32>::new();
+        let checked = Cell::new(false);
+        let mut f = pin!(async {
+            let mut receiver = mbox.receiver().unwrap();
+            mbox.post(1);
+            assert_eq!(receiver.next().await, Some(1));
+            // Coop budget is used up, so the receiver yields without a value.
+            assert!(futures::poll!(receiver.next()).is_pending());
+            assert_eq!(mbox.try_read(), Err(Error::AlreadyWaiting));
+            drop(receiver);
+            assert_eq!(mbox.try_read(), Ok(None));
+            checked.set(true);
+        });
+
+        LocalExecutor::new(&env)
+            .with_coop_budget(1)
+            .run([LocalFutureObj::new(&mut f)]);
+
+        assert!(checked.get());
+    }
 }
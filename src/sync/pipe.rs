@@ -0,0 +1,348 @@
+#![deny(unsafe_code)]
+
+use core::cell::{Cell, RefCell};
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use critical_section::{CriticalSection, Mutex};
+
+pub use crate::pipe::Error;
+use crate::pipe::Ring;
+
+/// Byte pipe with capacity of `N` bytes that can be used from other threads and interrupt
+/// handlers, for example to pass bytes received by UART ISR with `try_write`.
+pub struct Pipe<const N: usize> {
+    ring: Mutex<RefCell<Ring<N>>>,
+    reader: Mutex<Cell<Option<Waker>>>,
+    writer: Mutex<Cell<Option<Waker>>>,
+}
+
+impl<const N: usize> Debug for Pipe<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("sync::Pipe")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<const N: usize> Default for Pipe<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Pipe<N> {
+    /// Creates empty pipe.
+    pub const fn new() -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(Ring::new())),
+            reader: Mutex::new(Cell::new(None)),
+            writer: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Returns number of buffered bytes.
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).len())
+    }
+
+    /// Checks if there are no buffered bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits pipe into reading and writing halves.
+    pub fn split(&self) -> (Reader<'_, N>, Writer<'_, N>) {
+        (Reader { pipe: self }, Writer { pipe: self })
+    }
+
+    /// Waits for data and reads up to `buf.len()` bytes, returning number of bytes read.
+    /// Returns 0 only if `buf` is empty.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        critical_section::with(|cs| check_idle(self.reader.borrow(cs)))?;
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        Ok(ReadFuture { pipe: self, buf }.await)
+    }
+
+    /// Waits for free space and writes up to `data.len()` bytes, returning number of
+    /// bytes written. Returns 0 only if `data` is empty.
+    pub async fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        critical_section::with(|cs| check_idle(self.writer.borrow(cs)))?;
+
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        Ok(WriteFuture { pipe: self, data }.await)
+    }
+
+    /// Reads buffered bytes without waiting, returns number of bytes read.
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        let (count, waker) = critical_section::with(|cs| self.pop(cs, buf, None));
+        wake(waker);
+        count
+    }
+
+    /// Writes bytes that fit without waiting, returns number of bytes written.
+    pub fn try_write(&self, data: &[u8]) -> usize {
+        let (count, waker) = critical_section::with(|cs| self.push(cs, data, None));
+        wake(waker);
+        count
+    }
+
+    // Reads into `buf`, or registers `waiter` if there is nothing to read.
+    // Returns writer to wake outside of the critical section.
+    fn pop(
+        &self,
+        cs: CriticalSection,
+        buf: &mut [u8],
+        waiter: Option<&Waker>,
+    ) -> (usize, Option<Waker>) {
+        let count = self.ring.borrow_ref_mut(cs).pop(buf);
+        if count > 0 {
+            (count, self.writer.borrow(cs).take())
+        } else {
+            if let Some(waiter) = waiter {
+                self.reader.borrow(cs).set(Some(waiter.clone()));
+            }
+            (0, None)
+        }
+    }
+
+    // Writes `data`, or registers `waiter` if there is no space.
+    // Returns reader to wake outside of the critical section.
+    fn push(
+        &self,
+        cs: CriticalSection,
+        data: &[u8],
+        waiter: Option<&Waker>,
+    ) -> (usize, Option<Waker>) {
+        let count = self.ring.borrow_ref_mut(cs).push(data);
+        if count > 0 {
+            (count, self.reader.borrow(cs).take())
+        } else {
+            if let Some(waiter) = waiter {
+                self.writer.borrow(cs).set(Some(waiter.clone()));
+            }
+            (0, None)
+        }
+    }
+}
+
+fn check_idle(waker: &Cell<Option<Waker>>) -> Result<(), Error> {
+    match waker.take() {
+        Some(waker_value) => {
+            // Pipe busy.
+            // Restore waker and return error.
+            waker.set(Some(waker_value));
+            Err(Error::AlreadyWaiting)
+        }
+        None => Ok(()),
+    }
+}
+
+fn wake(waker: Option<Waker>) {
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+struct ReadFuture<'a, 'b, const N: usize> {
+    pipe: &'a Pipe<N>,
+    buf: &'b mut [u8],
+}
+
+impl<const N: usize> Future for ReadFuture<'_, '_, N> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        core::task::ready!(crate::executor::consume_budget(cx));
+
+        let this = self.get_mut();
+        let (count, waker) =
+            critical_section::with(|cs| this.pipe.pop(cs, this.buf, Some(cx.waker())));
+        wake(waker);
+
+        match count {
+            0 => Poll::Pending,
+            count => Poll::Ready(count),
+        }
+    }
+}
+
+impl<const N: usize> Drop for ReadFuture<'_, '_, N> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| self.pipe.reader.borrow(cs).set(None));
+    }
+}
+
+struct WriteFuture<'a, 'b, const N: usize> {
+    pipe: &'a Pipe<N>,
+    data: &'b [u8],
+}
+
+impl<const N: usize> Future for WriteFuture<'_, '_, N> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        core::task::ready!(crate::executor::consume_budget(cx));
+
+        let (count, waker) =
+            critical_section::with(|cs| self.pipe.push(cs, self.data, Some(cx.waker())));
+        wake(waker);
+
+        match count {
+            0 => Poll::Pending,
+            count => Poll::Ready(count),
+        }
+    }
+}
+
+impl<const N: usize> Drop for WriteFuture<'_, '_, N> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| self.pipe.writer.borrow(cs).set(None));
+    }
+}
+
+/// Reading half of `Pipe`.
+#[derive(Debug, Clone, Copy)]
+pub struct Reader<'a, const N: usize> {
+    pipe: &'a Pipe<N>,
+}
+
+impl<const N: usize> Reader<'_, N> {
+    /// See `Pipe::read`.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.pipe.read(buf).await
+    }
+}
+
+/// Writing half of `Pipe`.
+#[derive(Debug, Clone, Copy)]
+pub struct Writer<'a, const N: usize> {
+    pipe: &'a Pipe<N>,
+}
+
+impl<const N: usize> Writer<'_, N> {
+    /// See `Pipe::write`.
+    pub async fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pipe.write(data).await
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::ErrorType for Reader<'_, N> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::Read for Reader<'_, N> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.pipe.read(buf).await
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::ErrorType for Writer<'_, N> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<const N: usize> embedded_io_async::Write for Writer<'_, N> {
+    async fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.pipe.write(data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::join;
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::LocalExecutor;
+    use crate::test_utils::{CoreEnvironment, block_on};
+
+    #[test]
+    fn write_from_other_thread() {
+        let pipe = Pipe::<4>::new();
+        let env = CoreEnvironment::default();
+        let mut received = Vec::new();
+
+        std::thread::scope(|scope| {
+            // The writer poses as the other core, so its wakeups unpark the executor.
+            scope.spawn(|| {
+                env.enter(1);
+                let data: Vec<u8> = (0..50).collect();
+                let mut sent = 0;
+                while sent < data.len() {
+                    sent += pipe.try_write(&data[sent..]);
+                    std::thread::yield_now();
+                }
+            });
+
+            env.enter(0);
+            let mut consumer = pin!(async {
+                let (mut reader, _) = pipe.split();
+                let mut buf = [0; 3];
+                while received.len() < 50 {
+                    let count = reader.read(&mut buf).await.unwrap();
+                    received.extend_from_slice(&buf[..count]);
+                }
+            });
+
+            LocalExecutor::new(&env).run([LocalFutureObj::new(&mut consumer)]);
+        });
+
+        assert_eq!(received, (0..50).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn writer_waits_for_space() {
+        let pipe = Pipe::<2>::new();
+        pipe.try_write(&[1, 2]);
+
+        let (written, read) = block_on(async {
+            Some(join!(pipe.write(&[3, 4]), async {
+                let mut buf = [0; 4];
+                let count = pipe.read(&mut buf).await.unwrap();
+                buf[..count].to_vec()
+            }))
+        })
+        .expect("coroutine returned None");
+
+        assert_eq!(written, Ok(2));
+        assert_eq!(read, [1, 2]);
+
+        let mut buf = [0; 4];
+        assert_eq!(pipe.try_read(&mut buf), 2);
+        assert_eq!(buf[..2], [3, 4]);
+    }
+
+    #[test]
+    fn double_write_returns_error() {
+        let pipe = Pipe::<1>::new();
+        pipe.try_write(&[0]);
+
+        let (t, u, w) = block_on(async {
+            Some(join!(
+                async { pipe.write(&[1]).await },
+                async { pipe.write(&[2]).await },
+                async { pipe.try_read(&mut [0; 1]) }
+            ))
+        })
+        .expect("coroutine returned None");
+
+        assert_eq!(t, Ok(1));
+        assert_eq!(u, Err(Error::AlreadyWaiting));
+        assert_eq!(w, 1);
+    }
+}
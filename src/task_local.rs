@@ -0,0 +1,278 @@
+use core::cell::Cell;
+use core::fmt::Debug;
+use core::future::Future;
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::task::{Context, Poll};
+
+use critical_section::Mutex;
+use thiserror::Error;
+
+use crate::waker::WakerInfo;
+/// Declares task-local keys of type `LocalKey`.
+///
+/// ```
+/// async_scheduler::task_local! {
+///     static REQUEST_ID: u32;
+/// }
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty;)+) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::task_local::LocalKey<$t> = $crate::task_local::LocalKey::new();
+        )+
+    };
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Called outside of `scope()`.
+    #[error("task-local value not set")]
+    NotSet,
+}
+
+/// Key for a value visible to the future passed to `scope()` and everything it awaits.
+///
+/// Values are looked up by the executor and index of the polled task, so each task sees
+/// its own value even when several tasks or executors on other cores scope the same key.
+pub struct LocalKey<T> {
+    // Scopes being polled right now, innermost first.
+    scopes: Mutex<Scopes<T>>,
+}
+
+struct Scopes<T>(Cell<Option<NonNull<Scope<T>>>>);
+
+// SAFETY: the list is only accessed inside critical sections, and each value is only
+// read by the task which scoped it.
+unsafe impl<T> Send for Scopes<T> {}
+
+// Linked into the key while `TaskLocalFuture` is polled.
+struct Scope<T> {
+    task: WakerInfo,
+    value: *const T,
+    next: Cell<Option<NonNull<Scope<T>>>>,
+}
+
+impl<T> Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new() -> Self {
+        Self {
+            scopes: Mutex::new(Scopes(Cell::new(None))),
+        }
+    }
+
+    /// Runs `future` with the key set to `value`.
+    pub fn scope<F: Future>(&'static self, value: T, future: F) -> TaskLocalFuture<T, F> {
+        TaskLocalFuture {
+            key: self,
+            value,
+            future,
+        }
+    }
+
+    /// Calls `f` with the current value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not set, see `try_with()`.
+    pub async fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f).await.expect("task-local value not set")
+    }
+
+    /// Calls `f` with the current value, or returns error if the key is not set.
+    pub async fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, Error> {
+        let task = CurrentTask.await;
+        let value = critical_section::with(|cs| {
+            let mut node = self.scopes.borrow(cs).0.get();
+            while let Some(scope) = node {
+                // SAFETY: scopes are unlinked before their `poll` returns.
+                let scope = unsafe { scope.as_ref() };
+                if scope.task == task {
+                    return Some(scope.value);
+                }
+                node = scope.next.get();
+            }
+            None
+        });
+
+        // SAFETY: the scope belongs to this task, so it's being polled further up the stack.
+        value
+            .map(|value| f(unsafe { &*value }))
+            .ok_or(Error::NotSet)
+    }
+
+    /// Returns copy of the current value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not set.
+    pub async fn get(&'static self) -> T
+    where
+        T: Copy,
+    {
+        self.with(|value| *value).await
+    }
+}
+
+// Resolves to the executor and index of the polled task.
+struct CurrentTask;
+
+impl Future for CurrentTask {
+    type Output = WakerInfo;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(crate::waker::from_waker(cx.waker()))
+    }
+}
+
+/// Future returned by `LocalKey::scope()`.
+#[must_use = "futures do nothing unless polled"]
+pub struct TaskLocalFuture<T: 'static, F> {
+    key: &'static LocalKey<T>,
+    value: T,
+    future: F,
+}
+
+impl<T, F: Debug> Debug for TaskLocalFuture<T, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TaskLocalFuture")
+            .field("future", &self.future)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F: Future> Future for TaskLocalFuture<T, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved, other fields are not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let scope = Scope {
+            task: crate::waker::from_waker(cx.waker()),
+            value: &this.value,
+            next: Cell::new(None),
+        };
+        let _linked = Linked::new(this.key, &scope);
+
+        future.poll(cx)
+    }
+}
+
+// Keeps the scope linked until dropped, even if the inner future panics.
+struct Linked<'a, T> {
+    key: &'a LocalKey<T>,
+    scope: &'a Scope<T>,
+}
+
+impl<'a, T> Linked<'a, T> {
+    fn new(key: &'a LocalKey<T>, scope: &'a Scope<T>) -> Self {
+        critical_section::with(|cs| {
+            let head = &key.scopes.borrow(cs).0;
+            scope.next.set(head.get());
+            head.set(Some(NonNull::from(scope)));
+        });
+
+        Self { key, scope }
+    }
+}
+
+impl<T> Drop for Linked<'_, T> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| {
+            let this = NonNull::from(self.scope);
+            let mut link = &self.key.scopes.borrow(cs).0;
+            while let Some(node) = link.get() {
+                if node == this {
+                    link.set(self.scope.next.get());
+                    break;
+                }
+                // SAFETY: linked scopes stay in place until they unlink themselves.
+                link = unsafe { &node.as_ref().next };
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::join;
+
+    use super::*;
+    use crate::test_utils::block_on;
+
+    task_local! {
+        static REQUEST_ID: u32;
+    }
+
+    async fn current_id() -> u32 {
+        crate::yield_once().await;
+        REQUEST_ID.get().await
+    }
+
+    #[test]
+    fn concurrent_scopes() {
+        let (a, b) = block_on(async {
+            join!(
+                REQUEST_ID.scope(1, async { (current_id().await, current_id().await) }),
+                REQUEST_ID.scope(2, async { (current_id().await, current_id().await) }),
+            )
+        });
+
+        assert_eq!(a, (1, 1));
+        assert_eq!(b, (2, 2));
+    }
+
+    #[test]
+    fn nested_scope() {
+        let ids = block_on(async {
+            REQUEST_ID
+                .scope(1, async {
+                    let inner = REQUEST_ID.scope(2, current_id()).await;
+                    [current_id().await, inner, current_id().await]
+                })
+                .await
+        });
+
+        assert_eq!(ids, [1, 2, 1]);
+    }
+
+    #[test]
+    fn not_set_outside_scope() {
+        let result = block_on(async { Some(REQUEST_ID.try_with(|id| *id).await) })
+            .expect("coroutine returned None");
+
+        assert_eq!(result, Err(Error::NotSet));
+    }
+
+    #[test]
+    fn executors_on_two_threads() {
+        std::thread::scope(|scope| {
+            let workers = [1, 2].map(|id| {
+                scope.spawn(move || {
+                    block_on(REQUEST_ID.scope(id, async move {
+                        let mut seen = std::vec::Vec::new();
+                        for _ in 0..1000 {
+                            seen.push(current_id().await);
+                        }
+                        seen
+                    }))
+                })
+            });
+
+            for (id, worker) in [1, 2].into_iter().zip(workers) {
+                assert!(worker.join().unwrap().iter().all(|seen| *seen == id));
+            }
+        });
+    }
+}
@@ -0,0 +1,241 @@
+use core::cell::UnsafeCell;
+use core::fmt::Debug;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::sync::atomic::Ordering;
+
+use futures::task::{LocalFutureObj, UnsafeFutureObj};
+use portable_atomic::AtomicBool;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// All slots of the pool are taken by running tasks.
+    #[error("task pool exhausted")]
+    Exhausted,
+}
+
+/// Static storage for up to `M` tasks, each holding a future of up to `SIZE` bytes.
+///
+/// Spawns `'static` tasks without heap allocation and without keeping their futures
+/// on the caller's stack. Slots are type-erased, so futures of async blocks and
+/// `async fn` calls fit as long as they take up to `SIZE` bytes and are aligned to at
+/// most 8 bytes; other futures fail to compile. Futures are polled in place, the slot
+/// is freed when the task object is dropped.
+///
+/// ```
+/// use async_scheduler::task_pool::TaskPool;
+///
+/// static POOL: TaskPool<64, 4> = TaskPool::new();
+///
+/// async fn blink(times: u32) {
+///     for _ in 0..times {
+///         async_scheduler::yield_once().await;
+///     }
+/// }
+///
+/// let first = POOL.spawn(blink(3)).unwrap();
+/// let second = POOL.spawn(async { blink(5).await }).unwrap();
+/// ```
+pub struct TaskPool<const SIZE: usize, const M: usize> {
+    slots: [Slot<SIZE>; M],
+}
+
+// Must match alignment of `Storage`.
+const MAX_ALIGN: usize = 8;
+
+#[repr(C, align(8))]
+struct Storage<const SIZE: usize>([MaybeUninit<u8>; SIZE]);
+
+// Storage must be the first field: `PoolFuture::drop` finds the slot by its address.
+#[repr(C)]
+struct Slot<const SIZE: usize> {
+    future: UnsafeCell<Storage<SIZE>>,
+    taken: AtomicBool,
+}
+
+// SAFETY: slots are claimed in a critical section, and a claimed future is only reachable
+// through the `LocalFutureObj` returned by `spawn`, which is not `Send`.
+unsafe impl<const SIZE: usize, const M: usize> Sync for TaskPool<SIZE, M> {}
+
+impl<const SIZE: usize, const M: usize> Debug for TaskPool<SIZE, M> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TaskPool")
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+impl<const SIZE: usize, const M: usize> Default for TaskPool<SIZE, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const M: usize> TaskPool<SIZE, M> {
+    /// Creates pool with all slots free.
+    pub const fn new() -> Self {
+        Self {
+            slots: [const {
+                Slot {
+                    future: UnsafeCell::new(Storage([MaybeUninit::uninit(); SIZE])),
+                    taken: AtomicBool::new(false),
+                }
+            }; M],
+        }
+    }
+
+    /// Returns number of free slots.
+    pub fn available(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !slot.taken.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// Moves `future` into a free slot and returns task object to pass to the executor.
+    ///
+    /// The pool must be `'static`: a task object leaked with `mem::forget` never drops
+    /// its pinned future, which is only sound if the slot memory is never reused.
+    pub fn spawn<F: Future<Output = ()> + 'static>(
+        &'static self,
+        future: F,
+    ) -> Result<LocalFutureObj<'static, ()>, Error> {
+        const {
+            assert!(
+                size_of::<F>() <= SIZE,
+                "future doesn't fit into task pool slot"
+            );
+            assert!(
+                align_of::<F>() <= MAX_ALIGN,
+                "future is overaligned for task pool"
+            );
+        };
+
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| {
+                // Critical section instead of compare-exchange, which thumbv6m lacks.
+                critical_section::with(|_| {
+                    let free = !slot.taken.load(Ordering::Acquire);
+                    if free {
+                        slot.taken.store(true, Ordering::Relaxed);
+                    }
+                    free
+                })
+            })
+            .ok_or(Error::Exhausted)?;
+
+        // SAFETY: the slot was free, so nothing else refers to its storage, which is
+        // large and aligned enough for `F`.
+        unsafe { (slot.future.get() as *mut F).write(future) };
+
+        Ok(LocalFutureObj::new(PoolFuture::<SIZE, F> {
+            slot,
+            _future: PhantomData,
+        }))
+    }
+}
+
+struct PoolFuture<const SIZE: usize, F> {
+    slot: &'static Slot<SIZE>,
+    // Type of the future stored in the slot.
+    _future: PhantomData<F>,
+}
+
+// SAFETY: the future stays in its slot until `drop`, which frees the slot exactly once.
+unsafe impl<const SIZE: usize, F: Future<Output = ()> + 'static> UnsafeFutureObj<'static, ()>
+    for PoolFuture<SIZE, F>
+{
+    fn into_raw(self) -> *mut (dyn Future<Output = ()> + 'static) {
+        self.slot.future.get() as *mut F
+    }
+
+    unsafe fn drop(ptr: *mut (dyn Future<Output = ()> + 'static)) {
+        let future = ptr as *mut F;
+        unsafe {
+            core::ptr::drop_in_place(future);
+            let slot = &*(future as *const Slot<SIZE>);
+            slot.taken.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+
+    use portable_atomic::AtomicUsize;
+
+    use super::*;
+    use crate::executor::LocalExecutor;
+    use crate::test_utils::TestEnvironment;
+
+    async fn countdown(left: u32, finished: &'static AtomicUsize) {
+        for _ in 0..left {
+            crate::yield_once().await;
+        }
+        finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn spawn_into_static_pool() {
+        static POOL: TaskPool<64, 2> = TaskPool::new();
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+        // Futures of different, unnameable types share the pool.
+        let first = POOL.spawn(countdown(3, &FINISHED)).unwrap();
+        let second = POOL
+            .spawn(async {
+                crate::sleep(crate::time::Duration::new(5)).await;
+                countdown(5, &FINISHED).await;
+            })
+            .unwrap();
+        assert_eq!(
+            POOL.spawn(countdown(1, &FINISHED)).err(),
+            Some(Error::Exhausted)
+        );
+        assert_eq!(POOL.available(), 0);
+
+        let env = TestEnvironment::new();
+        LocalExecutor::new(&env).run([first, second]);
+
+        assert_eq!(FINISHED.load(Ordering::Relaxed), 2);
+        assert_eq!(POOL.available(), 2);
+    }
+
+    #[test]
+    fn drop_frees_slot() {
+        static POOL: TaskPool<8, 1> = TaskPool::new();
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Droppable;
+
+        impl Future for Droppable {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+                Poll::Pending
+            }
+        }
+
+        impl Drop for Droppable {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let task = POOL.spawn(Droppable).unwrap();
+        assert_eq!(POOL.available(), 0);
+
+        drop(task);
+
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        assert_eq!(POOL.available(), 1);
+        assert!(POOL.spawn(Droppable).is_ok());
+    }
+}
@@ -1,8 +1,9 @@
 use std::cell::Cell;
 use std::default::Default;
+use std::sync::atomic::Ordering;
 
 use futures::Future;
-use portable_atomic::AtomicBool;
+use portable_atomic::{AtomicBool, AtomicUsize};
 
 use crate::executor::{Environment, LocalExecutor};
 use crate::time::{Duration, Instant};
@@ -10,23 +11,31 @@ use crate::time::{Duration, Instant};
 #[derive(Debug)]
 pub(crate) struct TestEnvironment {
     tick: Cell<Instant>,
+    idle_count: Cell<usize>,
 }
 
 impl TestEnvironment {
     pub fn new() -> Self {
         Self {
             tick: Cell::new(Instant::new(0)),
+            idle_count: Cell::new(0),
         }
     }
 
     pub fn current_tick(&self) -> Instant {
         self.tick.get()
     }
+
+    /// Number of times executor went to sleep.
+    pub fn idle_count(&self) -> usize {
+        self.idle_count.get()
+    }
 }
 
 impl Environment for TestEnvironment {
     fn wait_for_event_with_deadline(&self, _event: &AtomicBool, _tick: Option<Instant>) {
         // No-op to allow timer to tick
+        self.idle_count.update(|count| count + 1);
     }
 
     fn ticks(&self) -> Instant {
@@ -51,3 +60,52 @@ pub fn block_on<T: Default>(future: impl Future<Output = T>) -> T {
 
     ret
 }
+
+/// Environment where each thread poses as a core, so wakers called from another
+/// thread go through `signal_core` like on multi-core parts.
+/// Idle parks the thread until another core signals it.
+#[derive(Debug, Default)]
+pub(crate) struct CoreEnvironment {
+    threads: std::sync::Mutex<[Option<std::thread::Thread>; 2]>,
+    signals: AtomicUsize,
+}
+
+std::thread_local! {
+    static CORE: Cell<usize> = const { Cell::new(0) };
+}
+
+impl CoreEnvironment {
+    /// Makes the calling thread act as `core`.
+    pub fn enter(&self, core: usize) {
+        CORE.set(core);
+        self.threads.lock().unwrap()[core] = Some(std::thread::current());
+    }
+
+    /// Number of times a core was signalled by another one.
+    pub fn signals(&self) -> usize {
+        self.signals.load(Ordering::Relaxed)
+    }
+}
+
+impl Environment for CoreEnvironment {
+    fn wait_for_event_with_deadline(&self, event: &AtomicBool, _tick: Option<Instant>) {
+        while !event.load(Ordering::Acquire) {
+            std::thread::park();
+        }
+    }
+
+    fn ticks(&self) -> Instant {
+        Instant::new(0)
+    }
+
+    fn core_id(&self) -> usize {
+        CORE.get()
+    }
+
+    fn signal_core(&self, core: usize) {
+        self.signals.fetch_add(1, Ordering::Relaxed);
+        if let Some(thread) = &self.threads.lock().unwrap()[core] {
+            thread.unpark();
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use core::cell::Cell;
 use core::task::{Context, Poll};
 
+use critical_section::Mutex;
+
 /// Point in time.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Instant(i64);
 
 impl Instant {
@@ -23,8 +28,17 @@ impl core::fmt::Display for Instant {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Instant {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "tick {=i64}", self.0)
+    }
+}
+
 /// Length of time interval between two Instants.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Duration(i64);
 
 impl Duration {
@@ -50,6 +64,17 @@ impl core::fmt::Display for Duration {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Duration {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        if self.0 == 1 {
+            defmt::write!(f, "1 tick")
+        } else {
+            defmt::write!(f, "{=i64} ticks", self.0)
+        }
+    }
+}
+
 impl core::ops::Add<Duration> for Instant {
     type Output = Self;
 
@@ -98,6 +123,62 @@ impl core::ops::Div<i64> for Duration {
     }
 }
 
+/// Extends a `BITS`-wide wrapping hardware counter into a monotonic `Instant`.
+///
+/// Counter overflows are detected by comparing each reading with the previous one,
+/// so the clock must be read more often than once per `PERIOD` ticks: a reading exactly
+/// `PERIOD` ticks after the previous one looks like no time has passed. Executor reads
+/// it on every iteration, but the environment must not sleep for `PERIOD` or longer
+/// either: clamp the wakeup deadline or read the clock from the overflow interrupt.
+///
+/// Down-counting timers (such as SysTick) should pass `mask - value` as the reading.
+#[derive(Debug)]
+pub struct WrappingClock<const BITS: u32> {
+    // Last raw reading and the number of ticks accumulated by previous wraps.
+    state: Mutex<Cell<(u32, i64)>>,
+}
+
+impl<const BITS: u32> WrappingClock<BITS> {
+    /// Number of ticks between counter overflows.
+    pub const PERIOD: Duration = Duration(1 << BITS);
+
+    const MASK: u32 = u32::MAX >> (32 - BITS);
+
+    /// Creates clock starting at tick 0.
+    pub const fn new() -> Self {
+        const { assert!(BITS > 0 && BITS <= 32, "counter width must be 1..=32 bits") };
+
+        Self {
+            state: Mutex::new(Cell::new((0, 0))),
+        }
+    }
+
+    /// Reads hardware counter with `read` and returns extended time.
+    ///
+    /// `read` is called inside a critical section, so an interrupt handler can't
+    /// observe a newer reading in the middle of the update and miscount a wrap.
+    pub fn now(&self, read: impl FnOnce() -> u32) -> Instant {
+        critical_section::with(|cs| {
+            let state = self.state.borrow(cs);
+            let (last, mut epoch) = state.get();
+            let raw = read() & Self::MASK;
+
+            if raw < last {
+                epoch += Self::PERIOD.0;
+            }
+            state.set((raw, epoch));
+
+            Instant(epoch + raw as i64)
+        })
+    }
+}
+
+impl<const BITS: u32> Default for WrappingClock<BITS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[must_use = "futures do nothing unless polled"]
 pub(crate) struct CurrentTime {}
@@ -112,11 +193,11 @@ impl Future for CurrentTime {
     type Output = Instant;
 
     fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Poll::Ready(
-            crate::waker::from_waker(cx.waker())
-                .executor()
-                .current_time(),
-        )
+        core::task::ready!(crate::executor::consume_budget(cx));
+
+        Poll::Ready(crate::waker::with_current(cx.waker(), |executor, _| {
+            executor.current_time()
+        }))
     }
 }
 
@@ -165,4 +246,42 @@ mod tests {
         let a = Duration::new(10);
         assert_eq!(a / 2, Duration::new(5));
     }
+
+    #[test]
+    fn test_wrapping_clock_extends_counter() {
+        let clock = WrappingClock::<8>::new();
+        assert_eq!(clock.now(|| 10), Instant::new(10));
+        assert_eq!(clock.now(|| 250), Instant::new(250));
+        assert_eq!(clock.now(|| 5), Instant::new(261));
+        assert_eq!(clock.now(|| 5), Instant::new(261));
+        assert_eq!(clock.now(|| 4), Instant::new(516));
+    }
+
+    #[test]
+    fn test_wrapping_clock_masks_reading() {
+        let clock = WrappingClock::<24>::new();
+        assert_eq!(clock.now(|| 0xff00_0010), Instant::new(0x10));
+        assert_eq!(WrappingClock::<24>::PERIOD, Duration::new(0x100_0000));
+    }
+
+    #[test]
+    fn test_wrapping_clock_full_width() {
+        let clock = WrappingClock::<32>::new();
+        assert_eq!(clock.now(|| u32::MAX), Instant::new(u32::MAX as i64));
+        assert_eq!(clock.now(|| 1), Instant::new((1 << 32) + 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let instant = Instant::new(-42);
+        let json = serde_json::to_string(&instant).unwrap();
+        assert_eq!(json, "-42");
+        assert_eq!(serde_json::from_str::<Instant>(&json).unwrap(), instant);
+
+        let duration = Duration::new(1000);
+        let json = serde_json::to_string(&duration).unwrap();
+        assert_eq!(json, "1000");
+        assert_eq!(serde_json::from_str::<Duration>(&json).unwrap(), duration);
+    }
 }
@@ -0,0 +1,353 @@
+#![deny(unsafe_code)]
+
+use core::cell::Cell;
+use core::fmt::Debug;
+
+use crate::executor::TaskMetadata;
+use crate::instrument::{Instrument, WakeSource};
+use crate::time::Instant;
+
+/// Size of binary records of `TraceEvent` and `replay::Decision`.
+pub(crate) const RECORD_SIZE: usize = 11;
+
+/// Encodes record as little-endian tick, task index and a byte of event data.
+pub(crate) fn encode_record(tick: Instant, task: u16, data: u8) -> [u8; RECORD_SIZE] {
+    let mut bytes = [0; RECORD_SIZE];
+    bytes[..8].copy_from_slice(&tick.ticks().to_le_bytes());
+    bytes[8..10].copy_from_slice(&task.to_le_bytes());
+    bytes[10] = data;
+    bytes
+}
+
+/// Decodes record written by `encode_record`.
+pub(crate) fn decode_record(bytes: &[u8; RECORD_SIZE]) -> (Instant, u16, u8) {
+    let mut tick = [0; 8];
+    tick.copy_from_slice(&bytes[..8]);
+
+    (
+        Instant::new(i64::from_le_bytes(tick)),
+        u16::from_le_bytes([bytes[8], bytes[9]]),
+        bytes[10],
+    )
+}
+
+/// Decodes a dump of records, stopping at the first one `from_bytes` rejects.
+pub(crate) fn decode_records<'a, T: 'a>(
+    bytes: &'a [u8],
+    from_bytes: fn(&[u8; RECORD_SIZE]) -> Option<T>,
+) -> impl Iterator<Item = T> + 'a {
+    bytes
+        .chunks_exact(RECORD_SIZE)
+        .map_while(move |chunk| from_bytes(chunk.try_into().ok()?))
+}
+
+/// Kind of recorded scheduler event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum EventKind {
+    PollStart = 0,
+    PollEnd = 1,
+    TimerWake = 2,
+    WakerWake = 3,
+    Sleep = 4,
+    Finished = 5,
+    IdleEnter = 6,
+    IdleExit = 7,
+}
+
+impl EventKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => EventKind::PollStart,
+            1 => EventKind::PollEnd,
+            2 => EventKind::TimerWake,
+            3 => EventKind::WakerWake,
+            4 => EventKind::Sleep,
+            5 => EventKind::Finished,
+            6 => EventKind::IdleEnter,
+            7 => EventKind::IdleExit,
+            _ => return None,
+        })
+    }
+}
+
+/// Scheduler event in compact form.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TraceEvent {
+    pub tick: Instant,
+    /// Task index, `NO_TASK` for executor-wide events.
+    pub task: u16,
+    pub kind: EventKind,
+}
+
+impl TraceEvent {
+    /// Task index of idle events.
+    pub const NO_TASK: u16 = u16::MAX;
+    /// Size of the binary encoding.
+    pub const SIZE: usize = RECORD_SIZE;
+
+    const EMPTY: Self = Self {
+        tick: Instant::new(0),
+        task: Self::NO_TASK,
+        kind: EventKind::IdleExit,
+    };
+
+    /// Encodes event as little-endian tick, task index and kind.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        encode_record(self.tick, self.task, self.kind as u8)
+    }
+
+    /// Decodes event written by `to_bytes`. Returns None for unknown event kind.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let (tick, task, kind) = decode_record(bytes);
+
+        Some(Self {
+            tick,
+            task,
+            kind: EventKind::from_u8(kind)?,
+        })
+    }
+}
+
+/// Decodes a dump of `TraceBuffer`, stopping at the first malformed event.
+pub fn decode(bytes: &[u8]) -> impl Iterator<Item = TraceEvent> + '_ {
+    decode_records(bytes, TraceEvent::from_bytes)
+}
+
+/// Fixed-size ring buffer of scheduler events, overwriting the oldest ones.
+/// Pass it to `LocalExecutor::with_instrument` by reference.
+pub struct TraceBuffer<const CAP: usize> {
+    events: [Cell<TraceEvent>; CAP],
+    // Index of the next event to write.
+    head: Cell<usize>,
+    len: Cell<usize>,
+    overwritten: Cell<usize>,
+}
+
+impl<const CAP: usize> Debug for TraceBuffer<CAP> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TraceBuffer")
+            .field("len", &self.len.get())
+            .field("overwritten", &self.overwritten.get())
+            .finish()
+    }
+}
+
+impl<const CAP: usize> Default for TraceBuffer<CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CAP: usize> TraceBuffer<CAP> {
+    /// Creates empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            events: [const { Cell::new(TraceEvent::EMPTY) }; CAP],
+            head: Cell::new(0),
+            len: Cell::new(0),
+            overwritten: Cell::new(0),
+        }
+    }
+
+    /// Appends event, overwriting the oldest one if buffer is full.
+    pub fn record(&self, event: TraceEvent) {
+        if CAP == 0 {
+            self.overwritten.update(|count| count + 1);
+            return;
+        }
+
+        let head = self.head.get();
+        self.events[head].set(event);
+        self.head.set((head + 1) % CAP);
+
+        if self.len.get() == CAP {
+            self.overwritten.update(|count| count + 1);
+        } else {
+            self.len.update(|len| len + 1);
+        }
+    }
+
+    /// Iterates over recorded events, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = TraceEvent> + '_ {
+        let start = self.head.get() + CAP - self.len.get();
+        (0..self.len.get()).map(move |offset| self.events[(start + offset) % CAP].get())
+    }
+
+    /// Number of events lost because buffer was full.
+    pub fn overwritten(&self) -> usize {
+        self.overwritten.get()
+    }
+
+    /// Writes binary encoding of recorded events to `out`, oldest first.
+    pub fn dump(&self, mut out: impl FnMut(&[u8])) {
+        self.iter().for_each(|event| out(&event.to_bytes()));
+    }
+
+    /// Removes all events.
+    pub fn clear(&self) {
+        self.len.set(0);
+        self.overwritten.set(0);
+    }
+
+    fn push(&self, task_index: usize, kind: EventKind, tick: Instant) {
+        self.record(TraceEvent {
+            tick,
+            task: u16::try_from(task_index).unwrap_or(TraceEvent::NO_TASK),
+            kind,
+        });
+    }
+}
+
+impl<const CAP: usize> Instrument for TraceBuffer<CAP> {
+    fn poll_start(&self, task: &TaskMetadata, tick: Instant) {
+        self.push(task.index, EventKind::PollStart, tick);
+    }
+
+    fn poll_end(&self, task: &TaskMetadata, tick: Instant) {
+        self.push(task.index, EventKind::PollEnd, tick);
+    }
+
+    fn task_woken(&self, task: &TaskMetadata, source: WakeSource, tick: Instant) {
+        let kind = match source {
+            WakeSource::Timer => EventKind::TimerWake,
+            WakeSource::Waker => EventKind::WakerWake,
+        };
+        self.push(task.index, kind, tick);
+    }
+
+    fn task_sleep(&self, task: &TaskMetadata, _deadline: Option<Instant>, tick: Instant) {
+        self.push(task.index, EventKind::Sleep, tick);
+    }
+
+    fn task_finished(&self, task: &TaskMetadata, tick: Instant) {
+        self.push(task.index, EventKind::Finished, tick);
+    }
+
+    fn idle_enter(&self, _deadline: Option<Instant>, tick: Instant) {
+        self.push(usize::MAX, EventKind::IdleEnter, tick);
+    }
+
+    fn idle_exit(&self, tick: Instant) {
+        self.push(usize::MAX, EventKind::IdleExit, tick);
+    }
+}
+
+/// Converts events into Chrome/Perfetto JSON trace format.
+/// Each task becomes a thread, polls and idle periods become slices.
+#[cfg(feature = "std")]
+pub fn chrome_trace(
+    events: impl IntoIterator<Item = TraceEvent>,
+    ticks_per_second: u64,
+) -> std::string::String {
+    use std::fmt::Write;
+
+    let mut json = std::string::String::from("{\"traceEvents\":[");
+
+    for (index, event) in events.into_iter().enumerate() {
+        let (name, phase) = match event.kind {
+            EventKind::PollStart => ("poll", "B"),
+            EventKind::PollEnd => ("poll", "E"),
+            EventKind::TimerWake => ("timer wake", "i"),
+            EventKind::WakerWake => ("waker wake", "i"),
+            EventKind::Sleep => ("sleep", "i"),
+            EventKind::Finished => ("finished", "i"),
+            EventKind::IdleEnter => ("idle", "B"),
+            EventKind::IdleExit => ("idle", "E"),
+        };
+        let micros = event.tick.ticks() as f64 * 1_000_000.0 / ticks_per_second as f64;
+
+        if index > 0 {
+            json.push(',');
+        }
+        // Writing into String never fails.
+        let _ = write!(
+            json,
+            "{{\"name\":\"{name}\",\"ph\":\"{phase}\",\"ts\":{micros},\"pid\":0,\"tid\":{}{}}}",
+            event.task,
+            if phase == "i" { ",\"s\":\"t\"" } else { "" }
+        );
+    }
+
+    json.push_str("]}");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::LocalExecutor;
+    use crate::test_utils::TestEnvironment;
+    use crate::time::Duration;
+
+    #[test]
+    fn ring_buffer_overwrites_oldest() {
+        let buffer = TraceBuffer::<3>::new();
+        for tick in 0..5 {
+            buffer.record(TraceEvent {
+                tick: Instant::new(tick),
+                task: 0,
+                kind: EventKind::PollStart,
+            });
+        }
+
+        let ticks: Vec<_> = buffer.iter().map(|event| event.tick.ticks()).collect();
+        assert_eq!(ticks, [2, 3, 4]);
+        assert_eq!(buffer.overwritten(), 2);
+    }
+
+    #[test]
+    fn dump_and_decode() {
+        let buffer = TraceBuffer::<64>::new();
+        let env = TestEnvironment::new();
+        let mut f = pin!(crate::sleep(Duration::new(5)));
+
+        LocalExecutor::new(&env)
+            .with_instrument(&buffer)
+            .run([LocalFutureObj::new(&mut f)]);
+
+        let mut bytes = Vec::new();
+        buffer.dump(|chunk| bytes.extend_from_slice(chunk));
+
+        let decoded: Vec<_> = decode(&bytes).collect();
+        assert_eq!(decoded, buffer.iter().collect::<Vec<_>>());
+        assert_eq!(
+            decoded.first().map(|event| event.kind),
+            Some(EventKind::PollStart)
+        );
+        assert_eq!(
+            decoded.last().map(|event| event.kind),
+            Some(EventKind::Finished)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn chrome_trace_format() {
+        let events = [
+            TraceEvent {
+                tick: Instant::new(1000),
+                task: 1,
+                kind: EventKind::PollStart,
+            },
+            TraceEvent {
+                tick: Instant::new(3000),
+                task: 1,
+                kind: EventKind::PollEnd,
+            },
+        ];
+
+        assert_eq!(
+            chrome_trace(events, 1_000_000),
+            "{\"traceEvents\":[\
+             {\"name\":\"poll\",\"ph\":\"B\",\"ts\":1000,\"pid\":0,\"tid\":1},\
+             {\"name\":\"poll\",\"ph\":\"E\",\"ts\":3000,\"pid\":0,\"tid\":1}]}"
+        );
+    }
+}
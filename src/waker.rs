@@ -1,64 +1,348 @@
-use core::task::{RawWaker, RawWakerVTable, Waker};
+use core::cell::Cell;
+use core::ptr::NonNull;
+use core::task::{Context, RawWaker, RawWakerVTable, Waker};
+
+use critical_section::Mutex;
 
 use crate::executor::Executor;
 
-#[derive(Debug)]
+// Waker data packs executor id into the high bits and task index into the low bits,
+// so wakers don't point into executors and can outlive them.
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+
+/// Largest number of tasks an executor can have.
+pub(crate) const MAX_TASKS: usize = INDEX_MASK;
+
+const MAX_EXECUTOR_ID: usize = usize::MAX >> INDEX_BITS;
+
+/// Task of a registered executor, as stored in its wakers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub(crate) struct WakerInfo {
+    executor_id: usize,
     task_index: usize,
-    executor: *const dyn Executor,
 }
 
 impl WakerInfo {
-    pub fn new(task_index: usize, executor: *const dyn Executor) -> Self {
-        Self {
-            task_index,
-            executor,
-        }
+    fn to_raw_waker(self) -> RawWaker {
+        let data = (self.executor_id << INDEX_BITS) | self.task_index;
+        RawWaker::new(data as *const (), &WAKER_VTABLE)
     }
 
-    pub const fn to_raw_waker(&self) -> RawWaker {
-        let ptr = self as *const Self as *const ();
-        RawWaker::new(ptr, &WAKER_VTABLE)
+    /// Calls `f` with the executor, or returns `None` if it's gone.
+    pub fn with_executor<R>(&self, f: impl FnOnce(&dyn Executor) -> R) -> Option<R> {
+        // Critical section keeps the executor from unregistering while `f` runs.
+        critical_section::with(|cs| {
+            let mut node = REGISTRY.borrow(cs).0.get();
+            while let Some(registration) = node {
+                // SAFETY: linked registrations stay in place until they unlink themselves.
+                let registration = unsafe { registration.as_ref() };
+                if registration.id.get() == self.executor_id {
+                    // SAFETY: the executor unlinks its registration before it's dropped.
+                    return registration
+                        .executor
+                        .get()
+                        .map(|executor| f(unsafe { &*executor }));
+                }
+                node = registration.next.get();
+            }
+            None
+        })
     }
 
-    pub fn executor(&self) -> &dyn Executor {
-        unsafe { self.executor.as_ref().expect("executor is null") }
+    fn wake_task(&self) {
+        // Wakers of dropped executors are ignored.
+        let _ = self.with_executor(|executor| executor.set_task_runnable(self.task_index));
     }
+}
 
-    pub fn task_index(&self) -> usize {
-        self.task_index
+pub(crate) fn from_waker(waker: &Waker) -> WakerInfo {
+    match PollWaker::from_waker(waker) {
+        Some(current) => current.info,
+        None => from_waker_data(waker.data()),
     }
+}
 
-    fn wake_task(&self) {
-        self.executor().set_task_runnable(self.task_index);
+/// Calls `f` with the executor polling the current task and the task's index.
+///
+/// # Panics
+///
+/// Panics if the task isn't polled by an executor of this crate.
+pub(crate) fn with_current<R>(waker: &Waker, f: impl FnOnce(&dyn Executor, usize) -> R) -> R {
+    if let Some(current) = PollWaker::from_waker(waker) {
+        // SAFETY: the executor is polling the task, so it's alive.
+        return f(unsafe { &*current.executor }, current.info.task_index);
     }
+
+    // Polled with a clone of the task's waker, e.g. by a combinator.
+    let info = from_waker_data(waker.data());
+    info.with_executor(|executor| f(executor, info.task_index))
+        .expect("task is not polled by async_scheduler executor")
+}
+
+// Waker the executor polls a task with. It points to the executor, so the task reaches
+// it without walking the registry; clones are registry wakers, as they may outlive it.
+struct PollWaker {
+    info: WakerInfo,
+    executor: *const dyn Executor,
 }
 
-pub(crate) fn from_waker(waker: &Waker) -> &WakerInfo {
-    unsafe {
-        (waker.data() as *const WakerInfo)
-            .as_ref()
-            .expect("waker has null data pointer")
+impl PollWaker {
+    fn from_waker(waker: &Waker) -> Option<&Self> {
+        // SAFETY: only `Registration::poll` makes wakers with this vtable, and they are
+        // borrowed by the polled task only while `PollWaker` is alive.
+        core::ptr::eq(waker.vtable(), &POLL_WAKER_VTABLE)
+            .then(|| unsafe { &*(waker.data() as *const Self) })
     }
+
+    fn wake_task(&self) {
+        // Critical section like registry wakers, see `take_flag`.
+        critical_section::with(|_| {
+            // SAFETY: the executor is polling the task, so it's alive.
+            unsafe { &*self.executor }.set_task_runnable(self.info.task_index);
+        });
+    }
+}
+
+// Static, so the vtable has a single address to recognize poll wakers by.
+static POLL_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    // clone()
+    |ptr| poll_waker(ptr).info.to_raw_waker(),
+    // wake()
+    |ptr| poll_waker(ptr).wake_task(),
+    // wake_by_ref()
+    |ptr| poll_waker(ptr).wake_task(),
+    // drop()
+    |_| {},
+);
+
+fn poll_waker<'a>(ptr: *const ()) -> &'a PollWaker {
+    // SAFETY: poll wakers point to a `PollWaker` outliving them.
+    unsafe { &*(ptr as *const PollWaker) }
 }
 
 const WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
     // clone()
     |ptr| RawWaker::new(ptr, &WAKER_VTABLE),
     // wake()
-    |ptr| unsafe {
-        debug_assert!(!ptr.is_null());
-        let waker = &*(ptr as *const WakerInfo);
-
-        waker.wake_task();
-    },
+    |ptr| from_waker_data(ptr).wake_task(),
     // wake_by_ref()
-    |ptr| unsafe {
-        debug_assert!(!ptr.is_null());
-        let waker = &*(ptr as *const WakerInfo);
-
-        waker.wake_task();
-    },
+    |ptr| from_waker_data(ptr).wake_task(),
     // drop()
     |_| {},
 );
+
+fn from_waker_data(ptr: *const ()) -> WakerInfo {
+    let data = ptr as usize;
+    WakerInfo {
+        executor_id: data >> INDEX_BITS,
+        task_index: data & INDEX_MASK,
+    }
+}
+
+// Ids wrap around after a while and skip registered executors, so wakers never reach
+// another live executor. A waker outliving its executor may reach a later one with
+// the same id, which only causes a spurious wakeup, as tasks must tolerate those.
+// Not an atomic counter, thumbv6m has no `fetch_add`.
+static NEXT_ID: Mutex<Cell<usize>> = Mutex::new(Cell::new(0));
+
+// Intrusive list of executors that have wakers.
+struct Head(Cell<Option<NonNull<Registration>>>);
+
+// SAFETY: the list is only accessed inside critical sections.
+unsafe impl Send for Head {}
+
+static REGISTRY: Mutex<Head> = Mutex::new(Head(Cell::new(None)));
+
+/// Entry of the executor registry, unlinked when dropped.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    // Assigned when linked.
+    id: Cell<usize>,
+    // Set while linked.
+    executor: Cell<Option<*const dyn Executor>>,
+    next: Cell<Option<NonNull<Registration>>>,
+}
+
+impl Registration {
+    pub fn new() -> Self {
+        Self {
+            id: Cell::new(0),
+            executor: Cell::new(None),
+            next: Cell::new(None),
+        }
+    }
+
+    /// Returns waker data of `task_index`.
+    pub fn waker_info(&self, task_index: usize) -> WakerInfo {
+        debug_assert!(task_index <= MAX_TASKS);
+
+        WakerInfo {
+            executor_id: self.id.get(),
+            task_index,
+        }
+    }
+
+    /// Calls `f` with the context to poll task `task_index` with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the registration isn't registered.
+    pub fn poll<R>(&self, task_index: usize, f: impl FnOnce(&mut Context<'_>) -> R) -> R {
+        let current = PollWaker {
+            info: self.waker_info(task_index),
+            executor: self.executor.get().expect("executor is not registered"),
+        };
+        // SAFETY: the waker is dropped before `current`, and its clones don't refer to it.
+        let waker = unsafe {
+            Waker::from_raw(RawWaker::new(
+                &current as *const PollWaker as *const (),
+                &POLL_WAKER_VTABLE,
+            ))
+        };
+
+        f(&mut Context::from_waker(&waker))
+    }
+
+    /// Makes wakers reach `executor`, does nothing if already registered.
+    ///
+    /// # Safety
+    ///
+    /// Registration must not move until dropped, and must be dropped before `executor`.
+    pub unsafe fn register(&self, executor: &dyn Executor) {
+        if self.executor.get().is_some() {
+            return;
+        }
+
+        // SAFETY: lifetime is erased, the caller guarantees the executor outlives registration.
+        let executor = unsafe {
+            core::mem::transmute::<*const dyn Executor, *const dyn Executor>(
+                executor as *const dyn Executor,
+            )
+        };
+
+        critical_section::with(|cs| {
+            let head = &REGISTRY.borrow(cs).0;
+            let next_id = NEXT_ID.borrow(cs);
+            let id = (0..=MAX_EXECUTOR_ID)
+                .map(|_| next_id.replace(next_id.get().wrapping_add(1)) & MAX_EXECUTOR_ID)
+                .find(|&id| !Self::is_registered(head, id))
+                .expect("too many executors");
+            self.id.set(id);
+            self.executor.set(Some(executor));
+            self.next.set(head.get());
+            head.set(Some(NonNull::from(self)));
+        });
+    }
+
+    fn is_registered(head: &Cell<Option<NonNull<Registration>>>, id: usize) -> bool {
+        let mut node = head.get();
+        while let Some(registration) = node {
+            // SAFETY: linked registrations stay in place until they unlink themselves.
+            let registration = unsafe { registration.as_ref() };
+            if registration.id.get() == id {
+                return true;
+            }
+            node = registration.next.get();
+        }
+        false
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if self.executor.get().is_none() {
+            return;
+        }
+
+        critical_section::with(|cs| {
+            let this = NonNull::from(&*self);
+            let mut link = &REGISTRY.borrow(cs).0;
+            while let Some(node) = link.get() {
+                if node == this {
+                    link.set(self.next.get());
+                    break;
+                }
+                // SAFETY: linked registrations stay in place until they unlink themselves.
+                link = unsafe { &node.as_ref().next };
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+    use core::task::Poll;
+
+    use futures::task::LocalFutureObj;
+
+    use super::*;
+    use crate::executor::{LocalExecutor, TaskMetadata};
+    use crate::test_utils::TestEnvironment;
+    use crate::time::{Duration, Instant};
+
+    #[derive(Debug)]
+    struct Idle;
+
+    impl Executor for Idle {
+        fn current_time(&self) -> Instant {
+            Instant::new(0)
+        }
+
+        fn wakeup_task_at(&self, _task_index: usize, _time: Instant) -> Poll<()> {
+            Poll::Pending
+        }
+
+        fn set_task_runnable(&self, _task_index: usize) {}
+
+        fn wake_all_tasks(&self) {}
+
+        fn shutdown(&self, _grace: Duration) {}
+
+        fn task_metadata(&self, task_index: usize) -> TaskMetadata {
+            TaskMetadata {
+                index: task_index,
+                name: None,
+                tag: None,
+                priority: 0,
+            }
+        }
+
+        fn consume_budget(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn ids_skip_registered_executors() {
+        let (first, second) = (Registration::new(), Registration::new());
+        // SAFETY: registrations are dropped before the executor.
+        unsafe { first.register(&Idle) };
+
+        // Wrap the counter around to the id in use.
+        critical_section::with(|cs| NEXT_ID.borrow(cs).set(first.id.get()));
+        // SAFETY: as above.
+        unsafe { second.register(&Idle) };
+
+        assert_ne!(first.id.get(), second.id.get());
+    }
+
+    #[test]
+    fn cloned_waker_reaches_executor() {
+        let env = TestEnvironment::new();
+        let mut task = pin!(async {
+            crate::sleep(Duration::new(5)).await;
+            // Poll with a registry waker instead of the one the executor passed.
+            let mut now = pin!(crate::now());
+            let now = futures::future::poll_fn(|cx| {
+                let waker = cx.waker().clone();
+                now.as_mut().poll(&mut Context::from_waker(&waker))
+            })
+            .await;
+            assert!(now >= Instant::new(5));
+        });
+
+        LocalExecutor::new(&env).run([LocalFutureObj::new(&mut task)]);
+    }
+}